@@ -0,0 +1,416 @@
+//! Plays planetwars matches between local bot executables,
+//! without the web server, the mozaic graph or the tcp endpoint.
+//!
+//! Every bot command is started with `sh -c`. Just like `client/runner.py` does,
+//! each turn the game state is written as one json line to the bot's stdin,
+//! and one line with the bot's action is read back from its stdout.
+//! A bot command like `ai:greedy` plays with a built-in AI instead.
+//!
+//! Logged games can be replayed through the rules, to verify that
+//! the log is consistent with them.
+
+extern crate planetwars;
+#[macro_use]
+extern crate serde_json;
+
+use planetwars::planetwars::pw_ai::Ai;
+use planetwars::planetwars::pw_clock::Clock;
+use planetwars::planetwars::pw_config::default_turn_timeout;
+use planetwars::planetwars::pw_log::{GameLog, LogHeader, LoggedAction};
+use planetwars::planetwars::pw_protocol as proto;
+use planetwars::planetwars::pw_rules::{CombatRule, Victory};
+use planetwars::planetwars::pw_stats::{DisqualifyPolicy, PlayerStats};
+use planetwars::planetwars::{self as pw, pw_delta, pw_replay, pw_rules, pw_serializer, Config};
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{self, Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage:
+    offline play [options] <map_file> <max_turns> <log_file> <bot command | ai:<name>>...
+    offline replay <log_file>
+    offline compact [--gzip] <log_file> <out_file>
+
+Options:
+    --turn-timeout <ms>
+    --time-bank <ms>
+    --consecutive-timeouts <n>  disqualify a bot after this many timeouts in a row
+    --parse-errors <n>          disqualify a bot after this many invalid turns
+    --error-budget <n>          disqualify a bot after this many timeouts, invalid turns and commands
+    --victory <condition>       last_standing, most_ships, most_planets or domination:<fraction>
+
+AIs: ai:random, ai:greedy, ai:defensive, ai:lookahead";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(|x| x.as_str()) {
+        Some("play") => play(&args[1..]),
+        Some("replay") if args.len() == 2 => replay(&args[1]),
+        Some("compact") if args.len() == 3 => compact(&args[1], &args[2], false),
+        Some("compact") if args.len() == 4 && args[1] == "--gzip" => {
+            compact(&args[2], &args[3], true)
+        }
+        _ => Err(USAGE.to_string()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// Plays one match and prints the outcome as json.
+fn play(mut args: &[String]) -> Result<(), String> {
+    let parse = |name: &str, value: &str| {
+        value
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid {} '{}'\n{}", name, value, USAGE))
+    };
+
+    let mut turn_timeout = default_turn_timeout();
+    let mut time_bank = None;
+    let mut disqualify = DisqualifyPolicy::default();
+    let mut victory = Victory::default();
+    loop {
+        match args {
+            [flag, value, rest @ ..] if flag == "--turn-timeout" => {
+                turn_timeout = parse("turn timeout", value)?;
+                args = rest;
+            }
+            [flag, value, rest @ ..] if flag == "--time-bank" => {
+                time_bank = Some(parse("time bank", value)?);
+                args = rest;
+            }
+            [flag, value, rest @ ..] if flag == "--consecutive-timeouts" => {
+                disqualify.consecutive_timeouts = Some(parse("timeout limit", value)?);
+                args = rest;
+            }
+            [flag, value, rest @ ..] if flag == "--parse-errors" => {
+                disqualify.parse_errors = Some(parse("parse error limit", value)?);
+                args = rest;
+            }
+            [flag, value, rest @ ..] if flag == "--error-budget" => {
+                disqualify.error_budget = Some(parse("error budget", value)?);
+                args = rest;
+            }
+            [flag, value, rest @ ..] if flag == "--victory" => {
+                victory = parse_victory(value)
+                    .ok_or_else(|| format!("Invalid victory '{}'\n{}", value, USAGE))?;
+                args = rest;
+            }
+            _ => break,
+        }
+    }
+
+    if args.len() < 4 {
+        return Err(USAGE.to_string());
+    }
+    disqualify.validate()?;
+
+    let config = Config {
+        map_file: args[0].clone(),
+        max_turns: parse("max_turns", &args[1])?,
+        combat: CombatRule::default(),
+        victory,
+        vision: None,
+        turn_timeout,
+        time_bank,
+        disqualify: Some(disqualify).filter(|policy| *policy != DisqualifyPolicy::default()),
+    };
+    config
+        .validate()
+        .map_err(|e| format!("Invalid config: {}", e))?;
+
+    let state = config
+        .create_game(args.len() - 3)
+        .map_err(|e| format!("Cannot create the game: {}", e))?;
+
+    let file = File::create(&args[2]).map_err(|e| format!("Cannot create log file: {}", e))?;
+
+    let players = args[3..]
+        .iter()
+        .map(|cmd| {
+            if cmd.starts_with(Ai::PREFIX) {
+                Ai::from_player(cmd)
+                    .map(Player::Ai)
+                    .ok_or_else(|| format!("Unknown AI '{}'\n{}", cmd, USAGE))
+            } else {
+                Bot::spawn(cmd)
+                    .map(Player::Bot)
+                    .map_err(|e| format!("Cannot start bot '{}': {}", cmd, e))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut log = GameLog::new(file);
+    log.write_header(&LogHeader::new(&config, &state, Vec::new()));
+
+    let mut game = OfflineGame::new(state, log, &config, players);
+    game.run();
+
+    println!(
+        "{}",
+        json!({
+            "winners": game.state.winners(),
+            "ranking": game.state.ranking(),
+            "turns": game.state.turn_num,
+            "map": config.map_file,
+            "file": args[2],
+            "stats": game.stats,
+        })
+    );
+
+    Ok(())
+}
+
+fn parse_victory(value: &str) -> Option<Victory> {
+    match value {
+        "last_standing" => Some(Victory::LastStanding),
+        "most_ships" => Some(Victory::MostShips),
+        "most_planets" => Some(Victory::MostPlanets),
+        _ => {
+            let fraction = value.strip_prefix("domination:")?.parse().ok()?;
+            Some(Victory::Domination(fraction))
+        }
+    }
+}
+
+/// Replays a game log, reporting the first turn where it diverges from the rules.
+fn replay(log_file: &str) -> Result<(), String> {
+    let lines = pw_delta::read_log(log_file).map_err(|e| format!("Cannot read log file: {}", e))?;
+
+    let turns = pw_replay::replay(lines).map_err(|e| e.to_string())?;
+    println!("Replayed {} turns, the log is consistent", turns);
+
+    Ok(())
+}
+
+/// Converts a game log to the compact format, the log can be in any format.
+fn compact(log_file: &str, out_file: &str, gzip: bool) -> Result<(), String> {
+    let lines = pw_delta::read_log(log_file).map_err(|e| format!("Cannot read log file: {}", e))?;
+    let compacted = pw_delta::compact(lines).map_err(|e| format!("Invalid log file: {}", e))?;
+    pw_delta::write_log(out_file, &compacted, gzip)
+        .map_err(|e| format!("Cannot write {}: {}", out_file, e))
+}
+
+/// How long a bot whose game is over has to read the final state and exit.
+const FINISH_TIME: Duration = Duration::from_millis(500);
+
+/// A bot process, its stdout lines are read on a separate thread,
+/// so we can wait for them with a timeout.
+/// Every line comes with the time it was read, to charge the bot's clock.
+struct Bot {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    lines: Receiver<(Instant, String)>,
+}
+
+impl Bot {
+    fn spawn(cmd: &str) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take().unwrap();
+
+        let (tx, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if tx.send((Instant::now(), line)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+        })
+    }
+
+    /// Sends one line to the bot, a bot that stopped reading is ignored from then on.
+    fn send(&mut self, line: &str) {
+        // Answers that came in too late belong to an earlier turn
+        while let Ok(_) = self.lines.try_recv() {}
+
+        if let Some(stdin) = self.stdin.as_mut() {
+            if writeln!(stdin, "{}", line).and_then(|_| stdin.flush()).is_err() {
+                self.stdin = None;
+            }
+        }
+    }
+
+    /// Sends the last state and closes stdin, a bot that does not exit in time is killed.
+    fn finish(&mut self, line: &str) {
+        self.send(line);
+        self.stdin = None;
+
+        let deadline = Instant::now() + FINISH_TIME;
+        while let Ok(None) = self.child.try_wait() {
+            if Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.kill();
+    }
+
+    fn kill(&mut self) {
+        self.stdin = None;
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Who plays a player: a bot process, or a built-in AI that needs no process.
+enum Player {
+    Bot(Bot),
+    Ai(Ai),
+}
+
+/// Drives `pw_rules::PlanetWars` the same way `PlanetWarsGame` does,
+/// with local processes instead of mozaic clients.
+struct OfflineGame {
+    state: pw_rules::PlanetWars,
+    planet_map: HashMap<String, usize>,
+    log: GameLog,
+    clock: Clock,
+    stats: Vec<PlayerStats>,
+    disqualify: Option<DisqualifyPolicy>,
+    players: Vec<Player>,
+}
+
+impl OfflineGame {
+    fn new(
+        state: pw_rules::PlanetWars,
+        log: GameLog,
+        config: &Config,
+        players: Vec<Player>,
+    ) -> Self {
+        Self {
+            planet_map: pw::planet_map(&state),
+            state,
+            log,
+            clock: Clock::new(config, players.len()),
+            stats: vec![PlayerStats::default(); players.len()],
+            disqualify: config.disqualify.clone(),
+            players,
+        }
+    }
+
+    fn run(&mut self) {
+        let mut alive = self.state.living_players();
+        self.dispatch_state(&alive, Vec::new());
+
+        while !self.state.is_finished() {
+            let turns = self.collect_turns(&alive);
+
+            self.state.repopulate();
+            let mut actions = Vec::new();
+            for (player_num, turn) in turns {
+                let (action, debug) =
+                    pw::execute_action(&mut self.state, &self.planet_map, player_num, turn);
+                if let proto::PlayerAction::ParseError(e) = &action {
+                    eprintln!("[player {}] {}", player_num + 1, e);
+                }
+                self.stats[player_num].record(&action);
+                actions.push(LoggedAction {
+                    player: player_num + 1,
+                    action,
+                    bank: self.clock.time_left(player_num).bank,
+                    debug,
+                });
+            }
+            self.state.step();
+            if let Some(policy) = &self.disqualify {
+                pw::disqualify_players(&mut self.state, policy, &mut self.stats);
+            }
+
+            self.dispatch_state(&alive, actions);
+            alive = self.state.living_players();
+        }
+    }
+
+    fn dispatch_state(&mut self, were_alive: &[usize], actions: Vec<LoggedAction>) {
+        self.log.log_turn(&self.state, actions);
+
+        for player in self
+            .state
+            .players
+            .iter()
+            .filter(|p| were_alive.contains(&p.id))
+        {
+            let bot = match &mut self.players[player.id] {
+                Player::Bot(bot) => bot,
+                Player::Ai(_) => continue,
+            };
+            let mut state = pw_serializer::serialize_player_view(&self.state, player.id);
+            state.time = Some(self.clock.time_left(player.id));
+            let state = serde_json::to_string(&state).unwrap();
+            if player.alive && !self.state.is_finished() {
+                bot.send(&state);
+            } else {
+                // Like the final state on the server, the bot sees how its game ended
+                bot.finish(&state);
+            }
+        }
+    }
+
+    /// Waits for the turn of every given player, a turn that is not in time results in `None`.
+    /// AI players decide right away, their time is not counted.
+    /// Like on the server, the turns of the bots come first and those of the AI players after.
+    fn collect_turns(&mut self, players: &[usize]) -> Vec<(usize, Option<String>)> {
+        let start = Instant::now();
+
+        let mut turns = Vec::new();
+        let mut ai_turns = Vec::new();
+        for &player_num in players {
+            let bot = match &self.players[player_num] {
+                Player::Bot(bot) => bot,
+                Player::Ai(ai) => {
+                    let action = ai.turn(&self.state, player_num);
+                    ai_turns.push((player_num, Some(serde_json::to_string(&action).unwrap())));
+                    continue;
+                }
+            };
+
+            let deadline = start + self.clock.allowed(player_num);
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let (used, turn) = match bot.lines.recv_timeout(timeout) {
+                Ok((time, line)) => (time.saturating_duration_since(start), Some(line)),
+                Err(_) => (start.elapsed(), None),
+            };
+
+            let in_time = self.clock.charge(player_num, used);
+            turns.push((player_num, turn.filter(|_| in_time)));
+        }
+
+        turns.extend(ai_turns);
+        turns
+    }
+}
+
+impl Drop for OfflineGame {
+    fn drop(&mut self) {
+        for player in self.players.iter_mut() {
+            if let Player::Bot(bot) = player {
+                bot.kill();
+            }
+        }
+    }
+}
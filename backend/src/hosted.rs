@@ -0,0 +1,583 @@
+//! Bots uploaded to the server, which the server runs itself.
+//!
+//! Every bot runs in a subprocess with resource limits, in its own namespaces
+//! where it sees neither the network nor the files of the server, the server relays between its stdin and stdout and the tcp endpoint,
+//! just like `client/runner.py` does for bots that are run by hand.
+
+use rand::Rng;
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::SystemTime;
+
+/// Longest bot name, names are also used as directory names.
+const MAX_NAME: usize = 32;
+
+/// Largest source file that can be uploaded, in bytes.
+const MAX_SOURCE: usize = 256 * 1024;
+
+/// Where the directories of replaced bots go, games that are still running may use them.
+/// It is emptied when the server starts.
+const REPLACED_DIR: &str = ".replaced";
+
+/// The languages bots can be uploaded in, a bot is a single source file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    Python,
+    Javascript,
+}
+
+impl Language {
+    fn file_name(&self) -> &'static str {
+        match self {
+            Language::Python => "bot.py",
+            Language::Javascript => "bot.js",
+        }
+    }
+
+    fn interpreter(&self) -> &'static str {
+        match self {
+            Language::Python => "python3",
+            Language::Javascript => "node",
+        }
+    }
+}
+
+/// An uploaded bot, its source is kept in its own directory.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HostedBot {
+    pub name: String,
+    pub language: Language,
+    pub uploaded: SystemTime,
+}
+
+/// An uploaded bot with the token of its owner, only the owner can replace it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct OwnedBot {
+    #[serde(flatten)]
+    bot: HostedBot,
+    /// Empty for bots uploaded before there were owners, these cannot be replaced.
+    #[serde(default)]
+    token: String,
+}
+
+/// Length of the owner tokens, and of the tokens of tournament bots.
+const TOKEN_LENGTH: usize = 32;
+
+/// What a bot process may use.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Limits {
+    /// Cpu time for the whole game, in seconds.
+    pub cpu_seconds: u64,
+    /// Heap size, in megabytes.
+    pub memory_mb: u64,
+    /// Size of the files a bot writes, in kilobytes.
+    pub file_kb: u64,
+    /// Processes and threads a bot can run at the same time.
+    pub processes: u64,
+    /// Runs the bot in its own user, mount, pid and network namespaces,
+    /// without network and with the server directory and `/tmp` hidden behind empty ones.
+    /// This needs unprivileged user namespaces.
+    #[serde(alias = "isolate_network")]
+    pub isolate: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            cpu_seconds: 120,
+            memory_mb: 512,
+            file_kb: 1024,
+            processes: 64,
+            isolate: true,
+        }
+    }
+}
+
+/// The namespaces of an isolated bot, killing the process kills everything the bot started.
+const UNSHARE: &str =
+    "unshare --user --map-root-user --mount --pid --fork --kill-child --mount-proc --net";
+
+/// Runs in the namespaces of an isolated bot, with the interpreter, the source,
+/// the bots directory and the server directory as arguments.
+/// The bot directory is mounted on `/tmp/bot` through the working directory,
+/// it may itself be under one of the hidden directories.
+const HIDE: &str = "mount -t tmpfs tmpfs /tmp && mkdir /tmp/bot \
+    && mount --no-canonicalize --bind /proc/self/cwd /tmp/bot \
+    && for dir in \"$2\" \"$3\"; do [ ! -d \"$dir\" ] || mount -t tmpfs tmpfs \"$dir\" || exit; done \
+    && cd /tmp/bot";
+
+impl Limits {
+    /// The command that runs the source file with the interpreter, within these limits.
+    /// The hidden directories have to be absolute.
+    fn command(&self, interpreter: &str, source: &Path, hidden: [&Path; 2]) -> Command {
+        // The data limit is used instead of the address space limit,
+        // node reserves far more address space than it ever uses.
+        let limits = format!(
+            "ulimit -t {} && ulimit -d {} && ulimit -f {}",
+            self.cpu_seconds,
+            self.memory_mb * 1024,
+            self.file_kb
+        );
+        // Within a user namespace the process limit only counts the processes of the bot
+        let run = format!("exec prlimit --nproc={} \"$0\" \"$1\"", self.processes);
+
+        let script = if self.isolate {
+            format!(
+                "{} && exec {} sh -c '{} && {}' \"$0\" \"$1\" \"$2\" \"$3\"",
+                limits, UNSHARE, HIDE, run
+            )
+        } else {
+            format!("{} && {}", limits, run)
+        };
+
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(script)
+            .arg(interpreter)
+            .arg(source)
+            .args(&hidden);
+        // Its own process group, so everything the bot started is stopped with it
+        command.process_group(0);
+        command
+    }
+
+    /// Checks that bots can be run with these limits, isolating them needs `unshare`
+    /// and unprivileged user namespaces.
+    fn check(&self) -> Result<(), String> {
+        if !self.isolate {
+            return Ok(());
+        }
+
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} true", UNSHARE))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match status {
+            Ok(status) if status.success() => Ok(()),
+            _ => Err(format!(
+                "`{}` failed, isolating bots needs unprivileged user namespaces",
+                UNSHARE
+            )),
+        }
+    }
+}
+
+/// A random secret, for owners of uploaded bots and bots in tournaments.
+pub fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(|x| x as char)
+        .collect()
+}
+
+/// Stops a bot and everything it started.
+fn stop(child: &mut Child) {
+    let _ = Command::new("kill")
+        .arg("-KILL")
+        .arg("--")
+        .arg(format!("-{}", child.id()))
+        .status();
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Why a bot cannot be uploaded.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum UploadError {
+    InvalidName(String),
+    SourceTooLarge,
+    /// The bot was uploaded by someone else, replacing it needs their token.
+    NotOwner(String),
+    Io(String),
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UploadError::InvalidName(name) => write!(
+                f,
+                "bot name {:?} is invalid, use at most {} letters, digits, - and _",
+                name, MAX_NAME
+            ),
+            UploadError::SourceTooLarge => {
+                write!(f, "the source is larger than {} bytes", MAX_SOURCE)
+            }
+            UploadError::NotOwner(name) => {
+                write!(
+                    f,
+                    "bot {} exists, replacing it needs the token of its owner",
+                    name
+                )
+            }
+            UploadError::Io(e) => write!(f, "saving the bot failed: {}", e),
+        }
+    }
+}
+
+/// All uploaded bots, the list is kept in `index.json` in their directory.
+pub struct HostedBots {
+    dir: PathBuf,
+    /// The tcp endpoint of the game manager, the bots connect to it.
+    endpoint: SocketAddr,
+    limits: Limits,
+    /// Why bots cannot be run with the limits, checked when they are set.
+    unavailable: Option<String>,
+    inner: Mutex<Vec<OwnedBot>>,
+}
+
+impl HostedBots {
+    pub fn open<P: AsRef<Path>>(dir: P, endpoint: SocketAddr) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let bots = match File::open(dir.join("index.json")) {
+            Ok(file) => serde_json::from_reader(file)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        match fs::remove_dir_all(dir.join(REPLACED_DIR)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => (),
+        }
+
+        Ok(HostedBots {
+            dir,
+            endpoint,
+            limits: Limits::default(),
+            unavailable: None,
+            inner: Mutex::new(bots),
+        })
+    }
+
+    /// Sets the limits of the bots, checking that bots can be run with them.
+    /// When they cannot, launching a bot fails.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.unavailable = limits.check().err();
+        if let Some(reason) = &self.unavailable {
+            println!("Hosted bots are disabled: {}", reason);
+        }
+        self.limits = limits;
+        self
+    }
+
+    pub fn get_bots(&self) -> Vec<HostedBot> {
+        self.inner
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|b| b.bot.clone())
+            .collect()
+    }
+
+    pub fn get_bot(&self, name: &str) -> Option<HostedBot> {
+        self.inner
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|b| b.bot.name == name)
+            .map(|b| b.bot.clone())
+    }
+
+    /// Checks a bot before it is uploaded.
+    pub fn check(name: &str, source: &str) -> Result<(), UploadError> {
+        let valid_name = !name.is_empty()
+            && name.len() <= MAX_NAME
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(UploadError::InvalidName(name.to_string()));
+        }
+
+        if source.len() > MAX_SOURCE {
+            return Err(UploadError::SourceTooLarge);
+        }
+
+        Ok(())
+    }
+
+    /// Saves the source of a bot, returning the bot with the token of its owner.
+    /// A new bot gets a new token, replacing a bot needs the token it was uploaded with.
+    /// The bot has to pass `check` first.
+    pub fn upload(
+        &self,
+        name: &str,
+        language: Language,
+        source: &str,
+        token: Option<&str>,
+    ) -> Result<(HostedBot, String), UploadError> {
+        let mut bots = self.inner.lock().unwrap();
+        let token = match bots.iter().find(|b| b.bot.name == name) {
+            Some(owned) if owned.token.is_empty() || token != Some(owned.token.as_str()) => {
+                return Err(UploadError::NotOwner(name.to_string()))
+            }
+            Some(owned) => owned.token.clone(),
+            None => generate_token(),
+        };
+
+        let bot = HostedBot {
+            name: name.to_string(),
+            language,
+            uploaded: SystemTime::now(),
+        };
+        self.write_source(name, language, source)
+            .map_err(|e| UploadError::Io(e.to_string()))?;
+
+        bots.retain(|b| b.bot.name != name);
+        bots.push(OwnedBot {
+            bot: bot.clone(),
+            token: token.clone(),
+        });
+        self.save(&bots)
+            .map_err(|e| UploadError::Io(e.to_string()))?;
+
+        Ok((bot, token))
+    }
+
+    /// Writes the source in a fresh directory and renames it to the directory of the bot.
+    /// The directory it replaces is moved aside, a running game may have it mounted.
+    fn write_source(&self, name: &str, language: Language, source: &str) -> io::Result<()> {
+        // Bot names have no dots, so these cannot be the directory of a bot
+        let new_dir = self.dir.join(format!(".new-{}", name));
+        if new_dir.exists() {
+            fs::remove_dir_all(&new_dir)?;
+        }
+        fs::create_dir_all(&new_dir)?;
+        fs::write(new_dir.join(language.file_name()), source)?;
+
+        let bot_dir = self.dir.join(name);
+        if bot_dir.exists() {
+            let replaced = self.dir.join(REPLACED_DIR);
+            fs::create_dir_all(&replaced)?;
+            let aside = replaced.join(format!("{}-{}", name, generate_token()));
+            fs::rename(&bot_dir, aside)?;
+        }
+        fs::rename(&new_dir, &bot_dir)
+    }
+
+    /// Starts an uploaded bot and connects it to a game with the given key.
+    /// The bot is stopped when the game closes the connection.
+    pub fn launch(&self, name: &str, key: u64) -> io::Result<()> {
+        // Uploads wait until the bot has started, the bot keeps its directory when it is replaced
+        let bots = self.inner.lock().unwrap();
+        let bot = match bots.iter().find(|b| b.bot.name == name) {
+            Some(owned) => owned.bot.clone(),
+            None => {
+                let message = format!("bot {} is not uploaded", name);
+                return Err(io::Error::new(io::ErrorKind::NotFound, message));
+            }
+        };
+        if let Some(reason) = &self.unavailable {
+            return Err(io::Error::new(io::ErrorKind::Other, reason.clone()));
+        }
+        let bots_dir = fs::canonicalize(&self.dir)?;
+        let server_dir = std::env::current_dir()?;
+        let bot_dir = bots_dir.join(name);
+
+        let mut stream = TcpStream::connect(self.endpoint)?;
+        writeln!(stream, "{}", json!({ "id": key, "name": name }))?;
+
+        let mut child = self
+            .limits
+            .command(
+                bot.language.interpreter(),
+                Path::new(bot.language.file_name()),
+                [&bots_dir, &server_dir],
+            )
+            .current_dir(&bot_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        drop(bots);
+
+        let stdout = child.stdout.take().unwrap();
+        let to_server = stream.try_clone()?;
+        thread::spawn(move || relay_turns(stdout, to_server));
+
+        let stdin = child.stdin.take().unwrap();
+        thread::spawn(move || relay_states(stream, stdin, child));
+
+        Ok(())
+    }
+
+    fn save(&self, bots: &[OwnedBot]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join("index.json");
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(bots).unwrap())?;
+        fs::rename(&tmp, &path)
+    }
+}
+
+/// Sends every line the bot writes to the game.
+fn relay_turns(stdout: ChildStdout, mut stream: TcpStream) {
+    for line in BufReader::new(stdout).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if writeln!(stream, "{}", line).is_err() {
+            break;
+        }
+    }
+}
+
+/// Hands the game states to the bot, until the game is over for it.
+fn relay_states(stream: TcpStream, mut stdin: ChildStdin, mut child: Child) {
+    for line in BufReader::new(&stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let message: serde_json::Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(_) => continue,
+        };
+
+        match message["type"].as_str() {
+            Some("game_state") => {
+                let state = message["content"].to_string();
+                if writeln!(stdin, "{}", state)
+                    .and_then(|_| stdin.flush())
+                    .is_err()
+                {
+                    // The bot crashed, the game goes on with timeouts
+                    continue;
+                }
+            }
+            Some("welcome") => {
+                // Uploaded bots only read states, they play with the defaults
+                if writeln!(&stream, "{{}}").is_err() {
+                    break;
+                }
+            }
+            Some("final_state") => break,
+            _ => {}
+        }
+    }
+
+    stop(&mut child);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hosted bots in an empty directory.
+    fn hosted(name: &str) -> HostedBots {
+        let dir = std::env::temp_dir().join(format!("hosted-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        HostedBots::open(dir, "127.0.0.1:9142".parse().unwrap()).unwrap()
+    }
+
+    fn source(hosted: &HostedBots, name: &str) -> String {
+        fs::read_to_string(hosted.dir.join(name).join("bot.py")).unwrap()
+    }
+
+    #[test]
+    fn names_and_sources_are_checked() {
+        assert_eq!(HostedBots::check("my_bot-2", "print()"), Ok(()));
+        assert_eq!(HostedBots::check(&"b".repeat(MAX_NAME), ""), Ok(()));
+        for name in ["", "../bot", ".replaced", "my bot", "bøt"].iter() {
+            assert_eq!(
+                HostedBots::check(name, ""),
+                Err(UploadError::InvalidName(name.to_string()))
+            );
+        }
+        let long = "b".repeat(MAX_NAME + 1);
+        assert_eq!(
+            HostedBots::check(&long, ""),
+            Err(UploadError::InvalidName(long.clone()))
+        );
+
+        assert_eq!(HostedBots::check("bot", &"#".repeat(MAX_SOURCE)), Ok(()));
+        assert_eq!(
+            HostedBots::check("bot", &"#".repeat(MAX_SOURCE + 1)),
+            Err(UploadError::SourceTooLarge)
+        );
+    }
+
+    #[test]
+    fn only_the_owner_replaces_a_bot() {
+        let hosted = hosted("owner");
+        let (_, token) = hosted.upload("bot", Language::Python, "1", None).unwrap();
+        assert_eq!(token.len(), TOKEN_LENGTH);
+
+        let not_owner = Err(UploadError::NotOwner("bot".to_string()));
+        let upload = |source, token| {
+            hosted
+                .upload("bot", Language::Python, source, token)
+                .map(|(_, token)| token)
+        };
+        assert_eq!(upload("2", None), not_owner);
+        assert_eq!(upload("2", Some("wrong")), not_owner);
+        assert_eq!(source(&hosted, "bot"), "1");
+
+        assert_eq!(upload("2", Some(&token)), Ok(token.clone()));
+        assert_eq!(source(&hosted, "bot"), "2");
+
+        // Another bot gets its own token
+        let (_, other) = hosted.upload("other", Language::Python, "3", None).unwrap();
+        assert_ne!(other, token);
+    }
+
+    #[test]
+    fn bots_without_an_owner_cannot_be_replaced() {
+        let hosted = hosted("unowned");
+        let bot = HostedBot {
+            name: "bot".to_string(),
+            language: Language::Python,
+            uploaded: SystemTime::now(),
+        };
+        let index = serde_json::to_string(&[bot]).unwrap();
+        fs::create_dir_all(&hosted.dir).unwrap();
+        fs::write(hosted.dir.join("index.json"), index).unwrap();
+        let hosted = HostedBots::open(&hosted.dir, hosted.endpoint).unwrap();
+
+        for token in [None, Some("")].iter() {
+            assert_eq!(
+                hosted
+                    .upload("bot", Language::Python, "2", *token)
+                    .map(|_| ()),
+                Err(UploadError::NotOwner("bot".to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn replaced_sources_are_kept_until_the_server_starts() {
+        let hosted = hosted("replace");
+        let (_, token) = hosted.upload("bot", Language::Python, "1", None).unwrap();
+
+        hosted
+            .upload("bot", Language::Python, "2", Some(&token))
+            .unwrap();
+        assert_eq!(source(&hosted, "bot"), "2");
+        let replaced: Vec<PathBuf> = fs::read_dir(hosted.dir.join(REPLACED_DIR))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(replaced.len(), 1);
+        assert_eq!(fs::read_to_string(replaced[0].join("bot.py")).unwrap(), "1");
+
+        let hosted = HostedBots::open(&hosted.dir, hosted.endpoint).unwrap();
+        assert!(!hosted.dir.join(REPLACED_DIR).exists());
+        assert_eq!(hosted.get_bots().len(), 1);
+        assert_eq!(source(&hosted, "bot"), "2");
+    }
+}
@@ -0,0 +1,15 @@
+//! The planetwars game itself, shared by the server and the offline tools.
+
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+
+extern crate flate2;
+extern crate futures;
+extern crate mozaic;
+extern crate planetwars_rules;
+extern crate rand;
+
+pub mod planetwars;
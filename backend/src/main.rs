@@ -37,10 +37,18 @@ use futures::future::FutureExt;
 use mozaic::graph;
 use mozaic::modules::*;
 
-mod planetwars;
+mod hosted;
+mod ratings;
 mod routes;
+mod store;
+mod tournament;
 mod util;
-use util::Games;
+use planetwars::planetwars;
+use hosted::{HostedBots, Limits};
+use planetwars::pw_feed::Feeds;
+use ratings::Ratings;
+use store::GameStore;
+use tournament::Tournaments;
 use util::COLOURS;
 
 use rocket::fairing::AdHoc;
@@ -48,6 +56,10 @@ use rocket_contrib::templates::tera::{self, Value};
 use rocket_contrib::templates::{Engines, Template};
 
 use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Port of the tcp endpoint bots connect to.
+const TCP_PORT: u16 = 9142;
 
 /// Config for the planetwars server
 #[derive(Deserialize, Serialize, Debug)]
@@ -55,6 +67,9 @@ pub struct PWConfig {
     host_name: String,
     address: String,
     port: u16,
+    /// What the bots the server runs itself may use.
+    #[serde(default)]
+    bot_limits: Limits,
 }
 
 impl Default for PWConfig {
@@ -63,6 +78,7 @@ impl Default for PWConfig {
             host_name: String::from("localhost"),
             address: String::from("0.0.0.0"),
             port: 8000,
+            bot_limits: Limits::default(),
         }
     }
 }
@@ -125,7 +141,20 @@ async fn rocket() -> rocket::Rocket {
 
     let pool = ThreadPool::builder().create().unwrap();
     pool.spawn_ok(fut.map(|_| ()));
-    let gm = create_game_manager("0.0.0.0:9142", pool.clone()).await;
+    let gm = Arc::new(create_game_manager(&format!("0.0.0.0:{}", TCP_PORT), pool.clone()).await);
+    let store = Arc::new(
+        GameStore::open("games/index.json", "games/games.json")
+            .expect("Opening the game store failed"),
+    );
+    let ratings =
+        Arc::new(Ratings::open("games/ratings.json").expect("Opening the ratings failed"));
+    ratings
+        .rate_finished(&store)
+        .expect("Rating finished games failed");
+    let tournaments = Arc::new(
+        Tournaments::open("games/tournaments.json").expect("Opening the tournaments failed"),
+    );
+    let feeds = Arc::new(Feeds::default());
 
     async_std::task::sleep(std::time::Duration::from_millis(200)).await;
 
@@ -136,10 +165,35 @@ async fn rocket() -> rocket::Rocket {
         .merge(Serialized::defaults(PWConfig::default()))   // Extend but not overwrite
         .merge(Env::prefixed("PW_"));                       // Overwrite
 
+    let bot_limits = figment
+        .extract::<PWConfig>()
+        .map(|config| config.bot_limits)
+        .unwrap_or_default();
+    let hosted = Arc::new(
+        HostedBots::open("bots", ([127, 0, 0, 1], TCP_PORT).into())
+            .expect("Opening the hosted bots failed")
+            .with_limits(bot_limits),
+    );
+
+    pool.spawn_ok(routes::finish_games(gm.clone(), store.clone(), ratings.clone()));
+    pool.spawn_ok(routes::run_tournaments(
+        tournaments.clone(),
+        pool.clone(),
+        gm.clone(),
+        store.clone(),
+        ratings.clone(),
+        feeds.clone(),
+        hosted.clone(),
+    ));
+
     rocket::custom(figment)
         .manage(gm)
         .manage(pool)
-        .manage(Games::new())
+        .manage(store)
+        .manage(ratings)
+        .manage(tournaments)
+        .manage(feeds)
+        .manage(hosted)
         .attach(AdHoc::config::<PWConfig>())    // Manage the config
         .mount("/", routes)
         .attach(AdHoc::on_attach("Assets Config", async move |rocket| {
@@ -3,81 +3,243 @@ use mozaic::modules::types::{Data, HostMsg, PlayerMsg};
 
 use serde_json;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::fs::{create_dir, File};
-use std::io::Write;
-use std::path::PathBuf;
-use std::time::SystemTime;
-
-mod pw_config;
-mod pw_protocol;
-mod pw_rules;
-mod pw_serializer;
-pub use pw_config::{Config, Map};
-use pw_protocol::{self as proto, CommandError};
-use pw_rules::Dispatch;
+use std::time::{Duration, Instant, SystemTime};
+
+pub mod pw_ai;
+pub mod pw_clock;
+pub mod pw_config;
+pub mod pw_delta;
+pub mod pw_feed;
+pub mod pw_log;
+pub mod pw_perspective;
+pub mod pw_replay;
+pub mod pw_stats;
+pub use planetwars_rules::commands::{execute_commands, planet_map};
+pub use planetwars_protocol as pw_protocol;
+pub use planetwars_rules::rules as pw_rules;
+pub use planetwars_rules::serializer as pw_serializer;
+pub use pw_config::{Config, ConfigError, Map, MapError};
+use pw_ai::Ai;
+use pw_clock::Clock;
+use pw_feed::Feed;
+use pw_log::{GameLog, LogHeader, LoggedAction};
+use pw_protocol as proto;
+use pw_stats::{Disqualification, DisqualifyPolicy, PlayerStats};
+
+/// How often the step lock hands the answers that arrived to the game.
+/// The game relies on the step lock calling `step` on every tick, also with some or none
+/// of the answers, that is how players that run out of time are noticed.
+/// An answer is handled at most a tick after it arrived, so every player is charged
+/// the time until the tick its answer was handled in, minus one tick of grace.
+pub const TICK: Duration = Duration::from_millis(50);
+
+/// How long the game waits for the answers to the welcome message,
+/// players that answer later, or not at all, play with the defaults.
+pub const HELLO_TIMEOUT: Duration = Duration::from_secs(1);
 
 pub struct PlanetWarsGame {
     state: pw_rules::PlanetWars,
+    config: Config,
+    /// The game starts once the players answered the welcome message.
+    started: bool,
+    /// The capabilities every player asked for in its answer, by player id.
+    capabilities: Vec<Vec<String>>,
+    /// The answers that arrived for the current turn, by player id.
+    /// `None` for players that ran out of time.
+    answers: HashMap<usize, Option<Data>>,
     planet_map: HashMap<String, usize>,
     log_file_loc: String,
-    log_file: File,
+    log: GameLog,
+    /// Spectators watching the game live.
+    feed: Option<Feed>,
+    clock: Clock,
+    stats: Vec<PlayerStats>,
+    disqualify: Option<DisqualifyPolicy>,
+    /// Players played by a built-in AI, they have no connection.
+    ai: Vec<(usize, Ai)>,
+    /// When the last state was sent to the players.
+    turn_start: Instant,
     turns: u64,
     name: String,
     map: String,
 }
 
 impl PlanetWarsGame {
-    pub fn new(state: pw_rules::PlanetWars, location: &str, name: &str, map: &str) -> Self {
-        let planet_map = state
-            .planets
-            .iter()
-            .map(|p| (p.name.clone(), p.id))
-            .collect();
+    pub fn new(
+        config: Config,
+        players: usize,
+        location: &str,
+        name: &str,
+    ) -> Result<Self, MapError> {
+        let state = config.create_game(players)?;
 
         if let Err(_) = create_dir("games") {
             println!("'games' already exists");
         }
 
-        let file = File::create(format!("games/{}", location)).unwrap();
+        let log = GameLog::new(File::create(format!("games/{}", location)).unwrap());
+        Ok(Self::with_state(config, state, log, location, name))
+    }
+
+    /// A game that starts from the given state, logging to the given log.
+    fn with_state(
+        config: Config,
+        state: pw_rules::PlanetWars,
+        log: GameLog,
+        location: &str,
+        name: &str,
+    ) -> Self {
+        let players = state.players.len();
+        let planet_map = planet_map(&state);
+        let map = config.map_name();
+        let clock = Clock::new(&config, players);
+        let disqualify = config.disqualify.clone();
 
         Self {
             state,
+            config,
+            started: false,
+            capabilities: vec![Vec::new(); players],
+            answers: HashMap::new(),
             planet_map,
             log_file_loc: location.to_string(),
-            log_file: file,
+            log,
+            feed: None,
+            clock,
+            stats: vec![PlayerStats::default(); players],
+            disqualify,
+            ai: Vec::new(),
+            turn_start: Instant::now(),
             turns: 0,
             name: name.to_string(),
-            map: PathBuf::from(map)
-                .file_stem()
-                .and_then(|x| x.to_str())
-                .unwrap()
-                .to_string(),
+            map,
         }
     }
 
-    fn dispatch_state(&mut self, were_alive: Vec<usize>, updates: &mut Vec<HostMsg>) {
-        let state = pw_serializer::serialize(&self.state);
-        write!(
-            self.log_file,
-            "{}\n",
-            serde_json::to_string(&state).unwrap()
-        )
-        .unwrap();
+    /// Publishes every turn of the game to a feed, the feed is closed when the game is over.
+    pub fn with_feed(mut self, feed: Feed) -> Self {
+        self.feed = Some(feed);
+        self
+    }
+
+    /// Lets built-in AIs play the given players, by player id.
+    pub fn with_ai(mut self, ai: Vec<(usize, Ai)>) -> Self {
+        self.ai = ai;
+        self
+    }
+
+    fn is_ai(&self, player_num: usize) -> bool {
+        self.ai.iter().any(|&(id, _)| id == player_num)
+    }
+
+    /// The turns of the living AI players, decided on the state that was sent to the players.
+    /// They are executed after the turns of the bots, the offline runner does the same.
+    fn ai_turns(&self) -> Vec<(usize, Option<Data>)> {
+        self.ai
+            .iter()
+            .filter(|&&(id, _)| self.state.players[id].alive)
+            .map(|&(id, ai)| {
+                let value = serde_json::to_string(&ai.turn(&self.state, id)).unwrap();
+                (id, Some(Data { value }))
+            })
+            .collect()
+    }
+
+    /// The living players whose answer did not arrive yet, AI players never answer.
+    fn waiting(&self) -> Vec<usize> {
+        self.state
+            .living_players()
+            .into_iter()
+            .filter(|id| !self.is_ai(*id) && !self.answers.contains_key(id))
+            .collect()
+    }
+
+    /// Reads the answers to the welcome message, once every player answered or
+    /// `HELLO_TIMEOUT` passed, the game starts with the first state.
+    /// Players that do not answer, or send something else, are fine with the defaults.
+    /// The log starts here, as replaying the game needs the capabilities of the players.
+    fn greet(&mut self, turns: Vec<PlayerMsg>, updates: &mut Vec<HostMsg>) {
+        for PlayerMsg { id, data } in turns {
+            let data = match data {
+                Some(data) => data,
+                None => continue,
+            };
+            let hello = serde_json::from_str::<proto::Hello>(&data.value);
+            self.answers.insert(id as usize, Some(data));
+            let hello = match hello {
+                Ok(hello) => hello,
+                Err(_) => continue,
+            };
+
+            if let Some(version) = hello
+                .protocol_version
+                .filter(|&v| v != proto::PROTOCOL_VERSION)
+            {
+                println!(
+                    "Player {} speaks protocol version {}, this is version {}",
+                    id + 1,
+                    version,
+                    proto::PROTOCOL_VERSION
+                );
+            }
+
+            let (known, unknown) = hello
+                .capabilities
+                .into_iter()
+                .partition(|c| pw_config::CAPABILITIES.contains(&c.as_str()));
+            for capability in unknown {
+                println!(
+                    "Player {} asks for unknown capability {}",
+                    id + 1,
+                    capability
+                );
+            }
+            self.capabilities[id as usize] = known;
+        }
+
+        if !self.waiting().is_empty() && self.turn_start.elapsed() < HELLO_TIMEOUT {
+            return;
+        }
+
+        self.answers.clear();
+        self.log.write_header(&LogHeader::new(
+            &self.config,
+            &self.state,
+            self.capabilities.clone(),
+        ));
+        self.started = true;
+        self.dispatch_state(self.state.living_players(), Vec::new(), updates);
+    }
+
+    fn dispatch_state(
+        &mut self,
+        were_alive: Vec<usize>,
+        actions: Vec<LoggedAction>,
+        updates: &mut Vec<HostMsg>,
+    ) {
+        let turn = self.log.log_turn(&self.state, actions);
+        if let Some(feed) = &self.feed {
+            feed.publish(turn);
+            if self.state.is_finished() {
+                feed.close();
+            }
+        }
 
         for player in self
             .state
             .players
             .iter()
-            .filter(|p| were_alive.contains(&p.id))
+            .filter(|p| were_alive.contains(&p.id) && !self.is_ai(p.id))
         {
-            let state = pw_serializer::serialize_rotated(&self.state, player.id);
-            let state = if player.alive && !self.state.is_finished() {
-                proto::ServerMessage::GameState(state)
-            } else {
-                proto::ServerMessage::FinalState(state)
-            };
+            let state = state_message(
+                &self.state,
+                player.id,
+                self.clock.time_left(player.id),
+                &self.capabilities[player.id],
+            );
 
             updates.push(HostMsg::Data(
                 Data {
@@ -90,88 +252,197 @@ impl PlanetWarsGame {
                 updates.push(HostMsg::Kick(player.id as u64));
             }
         }
+
+        self.turn_start = Instant::now();
     }
 
-    fn execute_commands<'a>(&mut self, turns: Vec<PlayerMsg>, updates: &mut Vec<HostMsg>) {
-        for PlayerMsg { id, data } in turns.into_iter() {
-            let player_num: usize = (id).try_into().unwrap();
-            let action = proto::ServerMessage::PlayerAction(self.execute_action(player_num, data));
-            let serialized_action = serde_json::to_string(&action).unwrap();
-            updates.push(HostMsg::Data(
-                Data {
-                    value: serialized_action,
-                },
-                Some(id),
-            ));
+    /// Records the answers that arrived this tick, charging every player the time it took,
+    /// and gives up on the players that are out of time.
+    /// An answer may have arrived up to a tick before it is handled, so a tick is not charged.
+    /// In the first turn, answers to the welcome message that came too late are skipped.
+    fn collect_answers(&mut self, turns: Vec<PlayerMsg>) {
+        let used = self.turn_start.elapsed();
+        let answered = used.saturating_sub(TICK);
+        let waiting = self.waiting();
+
+        for PlayerMsg { id, data } in turns {
+            let player_num: usize = id.try_into().unwrap();
+            let data = match data {
+                Some(data) if waiting.contains(&player_num) => data,
+                _ => continue,
+            };
+            if self.turns == 0 && is_hello(&data.value) {
+                continue;
+            }
+
+            let in_time = self.clock.charge(player_num, answered);
+            self.answers
+                .insert(player_num, Some(data).filter(|_| in_time));
+        }
+
+        for player_num in self.waiting() {
+            if used >= self.clock.allowed(player_num) {
+                self.clock.charge(player_num, used);
+                self.answers.insert(player_num, None);
+            }
         }
     }
 
-    fn execute_action<'a>(&mut self, player_num: usize, turn: Option<Data>) -> proto::PlayerAction {
-        let turn = match turn {
-            None => return proto::PlayerAction::Timeout,
-            Some(turn) => turn.value,
-        };
+    /// Plays a turn with the answers of every player.
+    fn play_turn(&mut self, updates: &mut Vec<HostMsg>) {
+        self.turns += 1;
 
-        let action: proto::Action = match serde_json::from_str(&turn) {
-            Err(err) => return proto::PlayerAction::ParseError(err.to_string()),
-            Ok(action) => action,
-        };
+        let alive = self.state.living_players();
+        let mut turns: Vec<(usize, Option<Data>)> = self.answers.drain().collect();
+        turns.sort_by_key(|&(id, _)| id);
+        turns.extend(self.ai_turns());
 
-        let commands = action
-            .commands
-            .into_iter()
-            .map(
-                |command| match self.check_valid_command(player_num, &command) {
-                    Ok(dispatch) => {
-                        self.state.dispatch(&dispatch);
-                        proto::PlayerCommand {
-                            command,
-                            error: None,
-                        }
-                    }
-                    Err(error) => proto::PlayerCommand {
-                        command,
-                        error: Some(error),
+        self.state.repopulate();
+        let actions = self.execute_commands(turns, updates);
+        self.state.step();
+        if let Some(policy) = &self.disqualify {
+            disqualify_players(&mut self.state, policy, &mut self.stats);
+        }
+
+        self.dispatch_state(alive, actions, updates);
+    }
+
+    fn execute_commands(
+        &mut self,
+        turns: Vec<(usize, Option<Data>)>,
+        updates: &mut Vec<HostMsg>,
+    ) -> Vec<LoggedAction> {
+        let mut actions = Vec::new();
+
+        for (player_num, data) in turns {
+            let is_ai = self.is_ai(player_num);
+            let (action, debug) = self.execute_action(player_num, data);
+            self.stats[player_num].record(&action);
+            if !is_ai {
+                let serialized_action =
+                    serde_json::to_string(&proto::ServerMessage::PlayerAction(action.clone()))
+                        .unwrap();
+                updates.push(HostMsg::Data(
+                    Data {
+                        value: serialized_action,
                     },
-                },
-            )
-            .collect();
+                    Some(player_num as u64),
+                ));
+            }
+
+            actions.push(LoggedAction {
+                player: player_num + 1,
+                action,
+                bank: self.clock.time_left(player_num).bank,
+                debug,
+            });
+        }
 
-        return proto::PlayerAction::Commands(commands);
+        actions
     }
 
-    fn check_valid_command(
-        &self,
+    fn execute_action(
+        &mut self,
         player_num: usize,
-        mv: &proto::Command,
-    ) -> Result<Dispatch, CommandError> {
-        let origin_id = *self
-            .planet_map
-            .get(&mv.origin)
-            .ok_or(CommandError::OriginDoesNotExist)?;
-
-        let target_id = *self
-            .planet_map
-            .get(&mv.destination)
-            .ok_or(CommandError::DestinationDoesNotExist)?;
+        turn: Option<Data>,
+    ) -> (proto::PlayerAction, Option<proto::Debug>) {
+        execute_action(
+            &mut self.state,
+            &self.planet_map,
+            player_num,
+            turn.map(|turn| turn.value),
+        )
+    }
+}
 
-        if self.state.planets[origin_id].owner() != Some(player_num) {
-            return Err(CommandError::OriginNotOwned);
+/// Longest debug text of a turn that is logged, in characters.
+const MAX_DEBUG_TEXT: usize = 1000;
+
+/// Executes the raw turn a player sent, dispatching all valid commands.
+/// No turn at all means the player timed out.
+/// Also returns the debug notes of the turn, to be logged.
+pub fn execute_action(
+    state: &mut pw_rules::PlanetWars,
+    planet_map: &HashMap<String, usize>,
+    player_num: usize,
+    turn: Option<String>,
+) -> (proto::PlayerAction, Option<proto::Debug>) {
+    let turn = match turn {
+        None => return (proto::PlayerAction::Timeout, None),
+        Some(turn) => turn,
+    };
+
+    let action: proto::Action = match serde_json::from_str(&turn) {
+        Err(err) => return (proto::PlayerAction::ParseError(err.to_string()), None),
+        Ok(action) => action,
+    };
+
+    let commands = execute_commands(state, planet_map, player_num, action.commands);
+    let debug = action.debug.map(|debug| {
+        // Known planets only and every planet once, so never more than the map has
+        let mut highlighted = HashSet::new();
+        proto::Debug {
+            text: debug.text.chars().take(MAX_DEBUG_TEXT).collect(),
+            planets: debug
+                .planets
+                .into_iter()
+                .filter(|name| planet_map.contains_key(name) && highlighted.insert(name.clone()))
+                .collect(),
         }
+    });
 
-        if self.state.planets[origin_id].ship_count() < mv.ship_count {
-            return Err(CommandError::NotEnoughShips);
-        }
+    (proto::PlayerAction::Commands(commands), debug)
+}
 
-        if mv.ship_count == 0 {
-            return Err(CommandError::ZeroShipMove);
+/// Disqualifies the living players that crossed a limit of the policy,
+/// recording the reason in their stats.
+pub fn disqualify_players(
+    state: &mut pw_rules::PlanetWars,
+    policy: &DisqualifyPolicy,
+    stats: &mut [PlayerStats],
+) {
+    for player_num in state.living_players() {
+        if let Some(reason) = policy.check(&stats[player_num]) {
+            state.disqualify(player_num);
+            stats[player_num].disqualified = Some(Disqualification {
+                turn: state.turn_num,
+                reason,
+            });
         }
+    }
+}
 
-        Ok(Dispatch {
-            origin: origin_id,
-            target: target_id,
-            ship_count: mv.ship_count,
-        })
+/// Whether a message looks like an answer to the welcome message,
+/// an object with nothing but the fields of `Hello`.
+fn is_hello(message: &str) -> bool {
+    match serde_json::from_str::<Value>(message) {
+        Ok(Value::Object(fields)) => fields
+            .keys()
+            .all(|key| key == "protocol_version" || key == "capabilities"),
+        _ => false,
+    }
+}
+
+/// The state a player receives after a turn, as the player asked for it.
+/// Final states are always complete.
+pub fn state_message(
+    state: &pw_rules::PlanetWars,
+    player_id: usize,
+    time: proto::TimeLeft,
+    capabilities: &[String],
+) -> proto::ServerMessage {
+    if !state.players[player_id].alive || state.is_finished() {
+        let mut view = pw_serializer::serialize_player_view(state, player_id);
+        view.time = Some(time);
+        proto::ServerMessage::FinalState(view)
+    } else if capabilities.iter().any(|c| c == proto::COMPACT_STATES) {
+        let mut view = pw_serializer::serialize_compact_view(state, player_id);
+        view.time = Some(time);
+        proto::ServerMessage::CompactState(view)
+    } else {
+        let mut view = pw_serializer::serialize_player_view(state, player_id);
+        view.time = Some(time);
+        proto::ServerMessage::GameState(view)
     }
 }
 
@@ -179,23 +450,35 @@ use serde_json::Value;
 
 impl game::Controller for PlanetWarsGame {
     fn start(&mut self) -> Vec<HostMsg> {
-        let mut updates = Vec::new();
-        self.dispatch_state(self.state.living_players(), &mut updates);
-        updates
+        self.turn_start = Instant::now();
+        let players = self.state.players.len();
+        (0..players)
+            .filter(|&id| !self.is_ai(id))
+            .map(|id| {
+                let welcome = proto::ServerMessage::Welcome(self.config.game_info(&self.state, id));
+                HostMsg::Data(
+                    Data {
+                        value: serde_json::to_string(&welcome).unwrap(),
+                    },
+                    Some(id as u64),
+                )
+            })
+            .collect()
     }
 
+    /// Called every tick with the answers that arrived since the last one, also when none did,
+    /// the turn is played once every living player answered or ran out of time.
     fn step(&mut self, turns: Vec<PlayerMsg>) -> Vec<HostMsg> {
-        self.turns += 1;
-
         let mut updates = Vec::new();
+        if !self.started {
+            self.greet(turns, &mut updates);
+            return updates;
+        }
 
-        let alive = self.state.living_players();
-
-        self.state.repopulate();
-        self.execute_commands(turns, &mut updates);
-        self.state.step();
-
-        self.dispatch_state(alive, &mut updates);
+        self.collect_answers(turns);
+        if self.waiting().is_empty() {
+            self.play_turn(&mut updates);
+        }
 
         updates
     }
@@ -203,18 +486,23 @@ impl game::Controller for PlanetWarsGame {
     fn state(&mut self) -> Value {
         json!({
             "map": self.map,
+            "file": self.log_file_loc,
         })
     }
 
     fn is_done(&mut self) -> Option<Value> {
         if self.state.is_finished() {
             Some(json!({
-                "winners": self.state.living_players(),
+                "winners": self.state.winners(),
+                "ranking": self.state.ranking(),
                 "turns": self.state.turn_num,
                 "name": self.name,
                 "map": self.map,
                 "file": self.log_file_loc,
                 "time": SystemTime::now(),
+                "combat": self.state.combat,
+                "victory": self.state.victory,
+                "stats": self.stats,
             }))
         } else {
             None
@@ -226,9 +514,13 @@ fn get_epoch() -> SystemTime {
     SystemTime::UNIX_EPOCH
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FinishedState {
+    /// The players ranked first.
     pub winners: Vec<u64>,
+    /// All players from best to worst, empty for games from before rankings.
+    #[serde(default)]
+    pub ranking: Vec<pw_rules::Ranked>,
     pub turns: u64,
     pub name: String,
     pub file: String,
@@ -236,4 +528,225 @@ pub struct FinishedState {
     #[serde(default = "get_epoch")]
     pub time: SystemTime,
     pub players: Vec<(u64, String)>,
+    #[serde(default)]
+    pub combat: pw_rules::CombatRule,
+    #[serde(default)]
+    pub victory: pw_rules::Victory,
+    /// Indexed by player id.
+    #[serde(default)]
+    pub stats: Vec<PlayerStats>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mozaic::modules::game::Controller;
+
+    fn config(turn_timeout: u64) -> Config {
+        Config {
+            map_file: String::new(),
+            max_turns: 100,
+            combat: Default::default(),
+            victory: Default::default(),
+            vision: None,
+            turn_timeout,
+            time_bank: None,
+            disqualify: None,
+        }
+    }
+
+    /// Every player owns a planet, the first one is called "a", the next "b" and so on.
+    fn game_of(players: usize) -> pw_rules::PlanetWars {
+        let planets = (0..players)
+            .map(|i| proto::Planet {
+                ship_count: 10,
+                x: i as f64 * 5.0,
+                y: 0.0,
+                owner: Some(i + 1),
+                name: ((b'a' + i as u8) as char).to_string(),
+                growth: 1,
+                radius: None,
+            })
+            .collect();
+        config(1000)
+            .create_game_on(Map { planets }, players)
+            .unwrap()
+    }
+
+    fn game() -> pw_rules::PlanetWars {
+        game_of(2)
+    }
+
+    const NO_MOVES: &str = r#"{"moves": []}"#;
+
+    /// A game with the given config, started by the players answering the welcome message.
+    fn controller(name: &str, config: Config) -> PlanetWarsGame {
+        controller_on(name, config, game())
+    }
+
+    fn controller_on(name: &str, config: Config, state: pw_rules::PlanetWars) -> PlanetWarsGame {
+        let path = std::env::temp_dir().join(format!("{}-{}.log", name, std::process::id()));
+        let log = GameLog::new(File::create(path).unwrap());
+        let players = state.players.len() as u64;
+        let mut controller = PlanetWarsGame::with_state(config, state, log, name, name);
+        controller.start();
+        controller.step((0..players).map(|id| answer(id, "{}")).collect());
+        assert!(controller.started);
+        controller
+    }
+
+    fn answer(id: u64, value: &str) -> PlayerMsg {
+        PlayerMsg {
+            id,
+            data: Some(Data {
+                value: value.to_string(),
+            }),
+        }
+    }
+
+    /// Pretends the last state was sent this long ago.
+    fn elapse(controller: &mut PlanetWarsGame, millis: u64) {
+        controller.turn_start = Instant::now() - Duration::from_millis(millis);
+    }
+
+    /// The actions the players are told about, by player id.
+    fn actions(updates: &[HostMsg]) -> Vec<(u64, proto::PlayerAction)> {
+        updates
+            .iter()
+            .filter_map(|update| match update {
+                HostMsg::Data(data, Some(id)) => match serde_json::from_str(&data.value) {
+                    Ok(proto::ServerMessage::PlayerAction(action)) => Some((*id, action)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn answers_handled_a_tick_late_are_in_time() {
+        let mut controller = controller("late-tick", config(100));
+        elapse(&mut controller, 100 + 40);
+        let updates = controller.step(vec![answer(0, NO_MOVES), answer(1, NO_MOVES)]);
+
+        let no_commands = proto::PlayerAction::Commands(Vec::new());
+        assert_eq!(
+            actions(&updates),
+            vec![(0, no_commands.clone()), (1, no_commands)]
+        );
+    }
+
+    #[test]
+    fn answers_handled_more_than_a_tick_late_time_out() {
+        let mut controller = controller("late", config(100));
+        elapse(&mut controller, 100 + 70);
+        let updates = controller.step(vec![answer(0, NO_MOVES), answer(1, NO_MOVES)]);
+
+        let timeout = proto::PlayerAction::Timeout;
+        assert_eq!(actions(&updates), vec![(0, timeout.clone()), (1, timeout)]);
+    }
+
+    #[test]
+    fn ticks_without_answers_time_out_the_missing_players() {
+        let mut controller = controller("ticks", config(100));
+        elapse(&mut controller, 20);
+        assert!(controller.step(vec![answer(0, NO_MOVES)]).is_empty());
+
+        // Ticks before the timeout wait for the missing answer
+        elapse(&mut controller, 70);
+        assert!(controller.step(Vec::new()).is_empty());
+
+        // The first tick after the timeout plays the turn without it
+        elapse(&mut controller, 100);
+        let updates = controller.step(Vec::new());
+        assert_eq!(
+            actions(&updates),
+            vec![
+                (0, proto::PlayerAction::Commands(Vec::new())),
+                (1, proto::PlayerAction::Timeout)
+            ]
+        );
+    }
+
+    #[test]
+    fn disqualified_players_lose_their_planets_and_expeditions() {
+        let mut state = game_of(3);
+        let planet_map = planet_map(&state);
+        let moves = json!({"moves": [{"origin": "a", "destination": "c", "ship_count": 4}]});
+        execute_action(&mut state, &planet_map, 0, Some(moves.to_string()));
+        execute_action(&mut state, &planet_map, 1, Some(moves.to_string()));
+        state.step();
+
+        let policy = DisqualifyPolicy {
+            parse_errors: Some(1),
+            ..Default::default()
+        };
+        let mut stats = vec![PlayerStats::default(); 3];
+        stats[0].record(&proto::PlayerAction::ParseError("garbage".to_string()));
+        disqualify_players(&mut state, &policy, &mut stats);
+
+        assert!(!state.players[0].alive);
+        assert_eq!(state.living_players(), vec![1, 2]);
+        assert_eq!(state.planets[0].owner(), None);
+        assert_eq!(state.planets[0].ship_count(), 6);
+        assert!(state.expeditions.iter().all(|e| e.fleet.owner != Some(0)));
+        assert_eq!(
+            stats[0].disqualified,
+            Some(Disqualification {
+                turn: 1,
+                reason: pw_stats::DisqualifyReason::ParseErrors(1),
+            })
+        );
+        assert_eq!(stats[1].disqualified, None);
+    }
+
+    #[test]
+    fn disqualified_players_get_the_final_state_and_are_kicked() {
+        let mut config = config(100);
+        config.disqualify = Some(DisqualifyPolicy {
+            consecutive_timeouts: Some(1),
+            ..Default::default()
+        });
+        let mut controller = controller_on("disqualify", config, game_of(3));
+        elapse(&mut controller, 20);
+        controller.step(vec![answer(0, NO_MOVES), answer(2, NO_MOVES)]);
+        elapse(&mut controller, 200);
+        let updates = controller.step(Vec::new());
+
+        let mut messages = Vec::new();
+        for update in updates {
+            match update {
+                HostMsg::Data(data, Some(id)) => match serde_json::from_str(&data.value).unwrap() {
+                    proto::ServerMessage::GameState(_) => messages.push((id, "state")),
+                    proto::ServerMessage::FinalState(_) => messages.push((id, "final")),
+                    _ => (),
+                },
+                HostMsg::Kick(id) => messages.push((id, "kick")),
+                _ => (),
+            }
+        }
+        assert_eq!(
+            messages,
+            vec![(0, "state"), (1, "final"), (1, "kick"), (2, "state")]
+        );
+        assert_eq!(controller.state.living_players(), vec![0, 2]);
+    }
+
+    #[test]
+    fn debug_notes_highlight_every_known_planet_once() {
+        let mut state = game();
+        let planet_map = planet_map(&state);
+        let mut planets = vec!["b", "x", "a", "b"];
+        planets.extend(std::iter::repeat("a").take(10_000));
+        let turn = json!({
+            "moves": [],
+            "debug": {"text": "y".repeat(5000), "planets": planets},
+        });
+
+        let (action, debug) = execute_action(&mut state, &planet_map, 0, Some(turn.to_string()));
+        let debug = debug.unwrap();
+        assert_eq!(action, proto::PlayerAction::Commands(Vec::new()));
+        assert_eq!(debug.planets, vec!["b", "a"]);
+        assert_eq!(debug.text.len(), MAX_DEBUG_TEXT);
+    }
 }
@@ -0,0 +1,575 @@
+//! Built-in AI players, so there is always an opponent to practice against.
+//!
+//! AI players are played by the game itself, without a connection.
+//! They decide on the same state as connected players, with fog of war they only
+//! see what a connected player would see.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+
+use planetwars_rules::Simulation;
+
+use super::pw_protocol as proto;
+use super::pw_rules::{Dispatch, Planet, PlanetWars, Player};
+use super::pw_serializer;
+
+/// Turns the lookahead AI simulates after every move it considers.
+const HORIZON: usize = 30;
+
+/// Targets the lookahead AI considers for every planet, the nearest ones.
+const LOOKAHEAD_TARGETS: usize = 8;
+
+/// Moves the lookahead AI makes in one turn at most.
+const LOOKAHEAD_MOVES: usize = 3;
+
+/// How long the lookahead AI thinks about a turn at most, on big maps it plays
+/// the best moves it found so far.
+const LOOKAHEAD_TIME: Duration = Duration::from_millis(100);
+
+/// The built-in AI players, from weakest to strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ai {
+    /// Sends random fleets from random planets.
+    Random,
+    /// Sends all ships of its strongest planet to the weakest planet it does not own,
+    /// like `static/bot/simple.py`.
+    Greedy,
+    /// Reinforces its threatened planets, only attacks planets it can surely take.
+    Defensive,
+    /// Simulates the game for every move it considers, playing the best ones.
+    Lookahead,
+}
+
+impl Ai {
+    /// Players are AI players when they are named `ai:<name>`, like `ai:greedy`.
+    pub const PREFIX: &'static str = "ai:";
+
+    pub const ALL: [Ai; 4] = [Ai::Random, Ai::Greedy, Ai::Defensive, Ai::Lookahead];
+
+    /// The AI for a player name like `ai:greedy`.
+    pub fn from_player(player: &str) -> Option<Ai> {
+        let name = player.strip_prefix(Ai::PREFIX)?;
+        Ai::ALL.iter().cloned().find(|ai| ai.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Ai::Random => "random",
+            Ai::Greedy => "greedy",
+            Ai::Defensive => "defensive",
+            Ai::Lookahead => "lookahead",
+        }
+    }
+
+    /// The turn of the AI playing the given player, in the state that was sent to the players.
+    /// The AI decides on the view the player was sent, in which it is the first player.
+    pub fn turn(&self, state: &PlanetWars, player_num: usize) -> proto::Action {
+        let view = view(state, player_num);
+        let dispatches = match self {
+            Ai::Random => random(&view, 0),
+            Ai::Greedy => greedy(&view, 0),
+            Ai::Defensive => defensive(&view, 0),
+            Ai::Lookahead => lookahead(&view, 0, Instant::now() + LOOKAHEAD_TIME),
+        };
+
+        proto::Action {
+            commands: dispatches
+                .iter()
+                .map(|d| proto::Command {
+                    origin: view.planets[d.origin].name.clone().into(),
+                    destination: view.planets[d.target].name.clone().into(),
+                    ship_count: d.ship_count,
+                })
+                .collect(),
+            debug: None,
+        }
+    }
+}
+
+/// The game as the given player sees it, with the same rules and players.
+/// Planets the player cannot see are left out, the player is player 0.
+/// A player cannot tell whether the players it does not see are still alive, so they all are.
+fn view(state: &PlanetWars, player_num: usize) -> PlanetWars {
+    let view = pw_serializer::serialize_player_view(state, player_num);
+    let mut view = Simulation::from_state(&view)
+        .with_combat(state.combat)
+        .with_victory(state.victory)
+        .with_turns_left(state.max_turns.saturating_sub(state.turn_num))
+        .state()
+        .clone();
+    view.players = (0..state.players.len())
+        .map(|id| Player {
+            id,
+            alive: true,
+            eliminated: None,
+        })
+        .collect();
+    view
+}
+
+fn owned(state: &PlanetWars, player_num: usize) -> Vec<&Planet> {
+    state
+        .planets
+        .iter()
+        .filter(|p| p.owner() == Some(player_num))
+        .collect()
+}
+
+fn random(state: &PlanetWars, player_num: usize) -> Vec<Dispatch> {
+    let mut rng = rand::thread_rng();
+    let origins: Vec<&Planet> = owned(state, player_num)
+        .into_iter()
+        .filter(|p| p.ship_count() > 1)
+        .collect();
+
+    let origin = match origins.choose(&mut rng) {
+        Some(origin) => origin,
+        None => return Vec::new(),
+    };
+    let target = match state
+        .planets
+        .iter()
+        .filter(|p| p.id != origin.id)
+        .collect::<Vec<_>>()
+        .choose(&mut rng)
+    {
+        Some(target) => target.id,
+        None => return Vec::new(),
+    };
+
+    vec![Dispatch {
+        origin: origin.id,
+        target,
+        ship_count: rng.gen_range(1..=origin.ship_count() / 2),
+    }]
+}
+
+/// On ties the first planet of the state is picked, just like simple.py does.
+fn greedy(state: &PlanetWars, player_num: usize) -> Vec<Dispatch> {
+    let origin = match owned(state, player_num)
+        .into_iter()
+        .min_by_key(|p| Reverse(p.ship_count()))
+    {
+        Some(origin) if origin.ship_count() > 1 => origin,
+        _ => return Vec::new(),
+    };
+
+    let target = state
+        .planets
+        .iter()
+        .filter(|p| p.owner() != Some(player_num))
+        .min_by_key(|p| p.ship_count());
+
+    target
+        .map(|target| Dispatch {
+            origin: origin.id,
+            target: target.id,
+            ship_count: origin.ship_count() - 1,
+        })
+        .into_iter()
+        .collect()
+}
+
+/// Ships on the way to a planet: those of the player, and those of everyone else.
+fn incoming(state: &PlanetWars, player_num: usize, planet: &Planet) -> (u64, u64) {
+    state
+        .expeditions
+        .iter()
+        .filter(|e| e.target == planet.id)
+        .fold((0, 0), |(own, enemy), e| {
+            if e.fleet.owner == Some(player_num) {
+                (own + e.fleet.ship_count, enemy)
+            } else {
+                (own, enemy + e.fleet.ship_count)
+            }
+        })
+}
+
+/// Ships needed to take a planet when they arrive from the given origin,
+/// 0 when the ships that are on their way already take it.
+fn needed(state: &PlanetWars, player_num: usize, origin: &Planet, target: &Planet) -> u64 {
+    let growth = if target.owner().is_some() {
+        target.growth * origin.distance(target)
+    } else {
+        0
+    };
+    let (own, enemy) = incoming(state, player_num, target);
+
+    (target.ship_count() + growth + enemy + 1).saturating_sub(own)
+}
+
+fn defensive(state: &PlanetWars, player_num: usize) -> Vec<Dispatch> {
+    let mine = owned(state, player_num);
+
+    // What every planet can spare, after keeping what it needs to hold itself
+    let mut spare: Vec<(usize, i64)> = mine
+        .iter()
+        .map(|p| {
+            let (own, enemy) = incoming(state, player_num, p);
+            let ships = p.ship_count() as i64 - 1;
+            (p.id, ships.min(ships + own as i64 - enemy as i64))
+        })
+        .collect();
+
+    let mut dispatches = Vec::new();
+    let send = |dispatches: &mut Vec<Dispatch>, origin: usize, target: usize, ships: u64| {
+        if ships > 0 {
+            dispatches.push(Dispatch {
+                origin,
+                target,
+                ship_count: ships,
+            });
+        }
+    };
+
+    // Reinforce threatened planets from the nearest planets with ships to spare
+    let threatened: Vec<(usize, i64)> = spare.iter().cloned().filter(|&(_, s)| s < 0).collect();
+    for (target, deficit) in threatened {
+        let mut deficit = -deficit;
+        let mut helpers: Vec<usize> = (0..spare.len()).filter(|&i| spare[i].1 > 0).collect();
+        helpers.sort_by_key(|&i| state.planets[spare[i].0].distance(&state.planets[target]));
+
+        for i in helpers {
+            if deficit <= 0 {
+                break;
+            }
+            let ships = deficit.min(spare[i].1);
+            spare[i].1 -= ships;
+            deficit -= ships;
+            send(&mut dispatches, spare[i].0, target, ships as u64);
+        }
+    }
+
+    // Only attack with ships that are not needed, and only planets that can surely be taken
+    for (origin, ships) in spare.iter_mut() {
+        let origin = &state.planets[*origin];
+        let target = state
+            .planets
+            .iter()
+            .filter(|p| p.owner() != Some(player_num))
+            .filter(|p| {
+                let needed = needed(state, player_num, origin, p);
+                needed > 0 && needed as i64 <= *ships / 2
+            })
+            .min_by_key(|p| origin.distance(p));
+
+        if let Some(target) = target {
+            let attackers = needed(state, player_num, origin, target);
+            *ships -= attackers as i64;
+            send(&mut dispatches, origin.id, target.id, attackers);
+        }
+    }
+
+    dispatches
+}
+
+/// How good a simulated state is for the player.
+fn score(state: &PlanetWars, player_num: usize) -> i64 {
+    let (_, ships) = state.holdings(player_num);
+    let growth: u64 = owned(state, player_num).iter().map(|p| p.growth).sum();
+    let enemies = state
+        .players
+        .iter()
+        .filter(|p| p.id != player_num)
+        .map(|p| state.holdings(p.id).1)
+        .max()
+        .unwrap_or(0);
+
+    ships as i64 + 10 * growth as i64 - enemies as i64
+}
+
+/// Plays the dispatches and lets the game go on for a while, the other players doing nothing.
+/// Only max turns cuts the game short: with fog of war, the players out of sight would
+/// count as dead after the first turn, which would end the game.
+fn simulate(state: &PlanetWars, player_num: usize, dispatches: &[Dispatch]) -> i64 {
+    let mut state = state.clone();
+    state.repopulate();
+    for dispatch in dispatches {
+        state.dispatch(dispatch);
+    }
+    state.step();
+
+    for _ in 0..HORIZON {
+        if state.turn_num >= state.max_turns {
+            break;
+        }
+        state.repopulate();
+        state.step();
+    }
+
+    score(&state, player_num)
+}
+
+/// Stops considering moves at the deadline, playing the best ones found so far.
+fn lookahead(state: &PlanetWars, player_num: usize, deadline: Instant) -> Vec<Dispatch> {
+    let mut dispatches = Vec::new();
+    let mut best = simulate(state, player_num, &dispatches);
+    // Ships on every planet that were not sent yet
+    let mut left: Vec<u64> = state.planets.iter().map(|p| p.ship_count()).collect();
+
+    for _ in 0..LOOKAHEAD_MOVES {
+        let mut candidates = Vec::new();
+        for origin in owned(state, player_num) {
+            if left[origin.id] <= 1 {
+                continue;
+            }
+
+            let mut targets: Vec<&Planet> =
+                state.planets.iter().filter(|p| p.id != origin.id).collect();
+            targets.sort_by_key(|p| origin.distance(p));
+
+            for target in targets.into_iter().take(LOOKAHEAD_TARGETS) {
+                let all = left[origin.id] - 1;
+                let enough = needed(state, player_num, origin, target);
+                for &ship_count in [all, enough].iter() {
+                    if ship_count > 0 && ship_count <= all {
+                        candidates.push(Dispatch {
+                            origin: origin.id,
+                            target: target.id,
+                            ship_count,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut best_candidate = None;
+        for candidate in candidates {
+            if Instant::now() >= deadline {
+                break;
+            }
+            dispatches.push(candidate);
+            let score = simulate(state, player_num, &dispatches);
+            let candidate = dispatches.pop().unwrap();
+            if score > best {
+                best = score;
+                best_candidate = Some(candidate);
+            }
+        }
+
+        match best_candidate {
+            Some(candidate) => {
+                left[candidate.origin] -= candidate.ship_count;
+                dispatches.push(candidate);
+            }
+            None => break,
+        }
+    }
+
+    dispatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planetwars::pw_config::{Config, Map};
+    use crate::planetwars::pw_rules::{Expedition, Fleet};
+    use planetwars_rules::commands::{execute_commands, planet_map};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn planet(name: &str, x: f64, y: f64, owner: Option<usize>, ship_count: u64) -> proto::Planet {
+        proto::Planet {
+            ship_count,
+            x,
+            y,
+            owner,
+            name: name.to_string(),
+            growth: 1,
+            radius: None,
+        }
+    }
+
+    fn game(planets: Vec<proto::Planet>, players: usize, vision: Option<f64>) -> PlanetWars {
+        let config = Config {
+            map_file: String::new(),
+            max_turns: 100,
+            combat: Default::default(),
+            victory: Default::default(),
+            vision,
+            turn_timeout: 1000,
+            time_bank: None,
+            disqualify: None,
+        };
+        config.create_game_on(Map { planets }, players).unwrap()
+    }
+
+    /// A 5 by 5 grid, the players start in the corners.
+    fn grid(players: usize, rng: &mut StdRng) -> Vec<proto::Planet> {
+        let corners = [0, 24, 4, 20];
+        (0..25)
+            .map(|i| {
+                let owner = corners[..players]
+                    .iter()
+                    .position(|&c| c == i)
+                    .map(|p| p + 1);
+                let (x, y) = ((i % 5) as f64 * 4.0, (i / 5) as f64 * 4.0);
+                planet(&format!("p{}", i), x, y, owner, rng.gen_range(0..6))
+            })
+            .collect()
+    }
+
+    /// What simple.py sends in the given state.
+    fn simple_py(state: &proto::State) -> Option<proto::Command> {
+        let mine: Vec<&proto::Planet> = state
+            .planets
+            .iter()
+            .filter(|p| p.owner == Some(1))
+            .collect();
+        let others: Vec<&proto::Planet> = state
+            .planets
+            .iter()
+            .filter(|p| p.owner != Some(1))
+            .collect();
+
+        // max and min in python return the first of equal planets
+        let mut origin = *mine.first()?;
+        for p in mine {
+            if p.ship_count > origin.ship_count {
+                origin = p;
+            }
+        }
+        let mut target = *others.first()?;
+        for p in others {
+            if p.ship_count < target.ship_count {
+                target = p;
+            }
+        }
+
+        Some(proto::Command {
+            origin: origin.name.clone().into(),
+            destination: target.name.clone().into(),
+            ship_count: origin.ship_count - 1,
+        })
+    }
+
+    #[test]
+    fn greedy_plays_like_simple_py() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..50 {
+            let mut state = game(grid(2, &mut rng), 2, None);
+            // Some more planets of the player, with ties in the ship counts
+            for planet in state.planets.iter_mut().filter(|p| p.id % 7 == 3) {
+                let ship_count = planet.ship_count() + 2;
+                planet.fleets = vec![Fleet {
+                    owner: Some(1),
+                    ship_count,
+                }];
+            }
+
+            for player in 0..2 {
+                let view = pw_serializer::serialize_player_view(&state, player);
+                let expected: Vec<proto::Command> = simple_py(&view)
+                    .into_iter()
+                    .filter(|c| c.ship_count > 0)
+                    .collect();
+                assert_eq!(Ai::Greedy.turn(&state, player).commands, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn defensive_reinforces_a_threatened_planet() {
+        let mut state = game(
+            vec![
+                planet("home", 0.0, 0.0, Some(1), 10),
+                planet("depot", 3.0, 0.0, Some(1), 50),
+                planet("far", 30.0, 0.0, None, 100),
+                planet("enemy", 20.0, 0.0, Some(2), 5),
+            ],
+            2,
+            None,
+        );
+        state.expeditions.push(Expedition {
+            id: 0,
+            origin: 3,
+            target: 0,
+            fleet: Fleet {
+                owner: Some(1),
+                ship_count: 30,
+            },
+            turns_remaining: 5,
+        });
+
+        let commands = Ai::Defensive.turn(&state, 0).commands;
+        // home keeps 1 ship of its own, and needs 21 more to hold against the 30
+        assert!(commands.contains(&proto::Command {
+            origin: "depot".into(),
+            destination: "home".into(),
+            ship_count: 21,
+        }));
+    }
+
+    #[test]
+    fn lookahead_returns_by_its_deadline() {
+        let planets = (0..400)
+            .map(|i| {
+                let owner = if i < 2 { Some(i + 1) } else { None };
+                let (x, y) = ((i % 20) as f64 * 3.0, (i / 20) as f64 * 3.0);
+                planet(&format!("p{}", i), x, y, owner, 50)
+            })
+            .collect();
+        let state = game(planets, 2, None);
+        let view = view(&state, 0);
+
+        let start = Instant::now();
+        lookahead(&view, 0, start + Duration::from_millis(20));
+        assert!(start.elapsed() < Duration::from_millis(20) + LOOKAHEAD_TIME);
+        assert!(lookahead(&view, 0, Instant::now()).is_empty());
+    }
+
+    #[test]
+    fn lookahead_sees_ahead_without_enemies_in_sight() {
+        let state = game(
+            vec![
+                planet("home", 0.0, 0.0, Some(1), 20),
+                planet("rich", 5.0, 0.0, None, 2),
+                planet("enemy", 100.0, 0.0, Some(2), 20),
+            ],
+            2,
+            Some(10.0),
+        );
+        let view = view(&state, 0);
+        assert_eq!(view.players.len(), 2);
+        assert_eq!(view.planets.len(), 2);
+        assert!(!view.is_finished());
+
+        let targets: Vec<proto::PlanetRef> = Ai::Lookahead
+            .turn(&state, 0)
+            .commands
+            .into_iter()
+            .map(|c| c.destination)
+            .collect();
+        assert!(targets.contains(&"rich".into()));
+    }
+
+    #[test]
+    fn no_ai_sends_a_refused_command() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for &vision in [None, Some(6.0)].iter() {
+            let mut state = game(grid(4, &mut rng), 4, vision);
+            let planet_map = planet_map(&state);
+
+            for _ in 0..25 {
+                // Like the server, every AI decides on the state that was sent to the players
+                let turns: Vec<(usize, proto::Action)> = state
+                    .living_players()
+                    .into_iter()
+                    .map(|id| (id, Ai::ALL[id].turn(&state, id)))
+                    .collect();
+
+                state.repopulate();
+                for (id, action) in turns {
+                    for command in execute_commands(&mut state, &planet_map, id, action.commands) {
+                        assert_eq!(command.error, None, "{:?}: {:?}", Ai::ALL[id], command);
+                    }
+                }
+                state.step();
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use super::pw_config::Config;
+use super::pw_protocol as proto;
+
+/// Chess clock of a game: every turn a player gets the turn timeout,
+/// time used over it comes out of the player's time bank.
+pub struct Clock {
+    turn_timeout: Duration,
+    /// Time left in the bank of every player, `None` when the game has no time bank.
+    banks: Option<Vec<Duration>>,
+}
+
+impl Clock {
+    pub fn new(config: &Config, players: usize) -> Self {
+        Clock {
+            turn_timeout: Duration::from_millis(config.turn_timeout),
+            banks: config
+                .time_bank
+                .map(|bank| vec![Duration::from_millis(bank); players]),
+        }
+    }
+
+    /// The longest the player can take for its next turn.
+    pub fn allowed(&self, player_num: usize) -> Duration {
+        self.turn_timeout.saturating_add(self.bank(player_num))
+    }
+
+    /// Charges the player for a turn that took `used`.
+    /// Returns whether the turn was in time, a late turn empties the bank.
+    pub fn charge(&mut self, player_num: usize, used: Duration) -> bool {
+        if used <= self.turn_timeout {
+            return true;
+        }
+
+        let over = used - self.turn_timeout;
+        match self.banks.as_mut() {
+            Some(banks) if over <= banks[player_num] => {
+                banks[player_num] -= over;
+                true
+            }
+            Some(banks) => {
+                banks[player_num] = Duration::from_secs(0);
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub fn time_left(&self, player_num: usize) -> proto::TimeLeft {
+        proto::TimeLeft {
+            turn: self.turn_timeout.as_millis() as u64,
+            bank: self
+                .banks
+                .as_ref()
+                .map(|_| self.bank(player_num).as_millis() as u64),
+        }
+    }
+
+    fn bank(&self, player_num: usize) -> Duration {
+        self.banks
+            .as_ref()
+            .map_or(Duration::from_secs(0), |banks| banks[player_num])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(turn_timeout: u64, time_bank: Option<u64>) -> Clock {
+        let config = Config {
+            map_file: "maps/hex.json".to_string(),
+            max_turns: 100,
+            combat: Default::default(),
+            victory: Default::default(),
+            vision: None,
+            turn_timeout,
+            time_bank,
+            disqualify: None,
+        };
+        Clock::new(&config, 2)
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn without_a_bank_only_the_turn_timeout_counts() {
+        let mut clock = clock(100, None);
+        assert_eq!(clock.allowed(0), ms(100));
+        assert!(clock.charge(0, ms(100)));
+        assert!(!clock.charge(0, ms(101)));
+        assert_eq!(clock.time_left(0).bank, None);
+    }
+
+    #[test]
+    fn overtime_comes_out_of_the_players_own_bank() {
+        let mut clock = clock(100, Some(500));
+        assert_eq!(clock.allowed(0), ms(600));
+
+        assert!(clock.charge(0, ms(300)));
+        assert!(clock.charge(1, ms(50)));
+        assert_eq!(clock.allowed(0), ms(400));
+        assert_eq!(clock.allowed(1), ms(600));
+        assert_eq!(clock.time_left(0).turn, 100);
+        assert_eq!(clock.time_left(0).bank, Some(300));
+    }
+
+    #[test]
+    fn huge_times_do_not_overflow() {
+        let clock = clock(u64::MAX, Some(u64::MAX));
+        assert!(clock.allowed(0) >= ms(u64::MAX));
+    }
+
+    #[test]
+    fn a_late_turn_empties_the_bank() {
+        let mut clock = clock(100, Some(500));
+        assert!(!clock.charge(1, ms(700)));
+        assert_eq!(clock.allowed(1), ms(100));
+        assert_eq!(clock.time_left(1).bank, Some(0));
+        assert_eq!(clock.allowed(0), ms(600));
+    }
+}
@@ -1,42 +1,143 @@
+use std::collections::HashSet;
+use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::Read;
+use std::path::Path;
 
 use serde_json;
 
 use super::pw_protocol as proto;
 use super::pw_rules::*;
+use super::pw_serializer;
+use super::pw_stats::DisqualifyPolicy;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub map_file: String,
     pub max_turns: u64,
+    #[serde(default)]
+    pub combat: CombatRule,
+    #[serde(default)]
+    pub victory: Victory,
+    /// Vision radius of the players, enables fog of war.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision: Option<f64>,
+    /// Time a player gets for every turn, in milliseconds.
+    #[serde(default = "default_turn_timeout")]
+    pub turn_timeout: u64,
+    /// Time every player can spend over the turn timeout during the whole game, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_bank: Option<u64>,
+    /// Without a policy, players are never disqualified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disqualify: Option<DisqualifyPolicy>,
 }
 
+/// What the server can do on request of a player, sent in the welcome message.
+pub const CAPABILITIES: &[&str] = &[proto::COMPACT_STATES];
+
+pub fn default_turn_timeout() -> u64 {
+    1000
+}
+
+/// The longest turn timeout, in milliseconds.
+pub const MAX_TURN_TIMEOUT: u64 = 60 * 1000;
+
+/// The largest time bank, in milliseconds.
+pub const MAX_TIME_BANK: u64 = 60 * 60 * 1000;
+
 impl Config {
-    pub fn create_game(&self, clients: usize) -> PlanetWars {
-        let planets = self.load_map(clients);
+    /// Checks the rules and times of the config, the map is checked when the game is created.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Victory::Domination(fraction) = self.victory {
+            // written this way to refuse NaN as well
+            if !(fraction > 0.0 && fraction <= 1.0) {
+                return Err(ConfigError::InvalidDomination(fraction));
+            }
+        }
+        if let Some(radius) = self.vision {
+            if !(radius > 0.0) {
+                return Err(ConfigError::InvalidVision(radius));
+            }
+        }
+        if self.turn_timeout > MAX_TURN_TIMEOUT {
+            return Err(ConfigError::TurnTimeoutTooLong(self.turn_timeout));
+        }
+        match self.time_bank {
+            Some(bank) if bank > MAX_TIME_BANK => Err(ConfigError::TimeBankTooLarge(bank)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The name of the map, its file name without extension.
+    pub fn map_name(&self) -> String {
+        Path::new(&self.map_file)
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// The welcome message of a player in a game with this config, by player id.
+    pub fn game_info(&self, state: &PlanetWars, player_id: usize) -> proto::GameInfo {
+        proto::GameInfo {
+            protocol_version: proto::PROTOCOL_VERSION,
+            player: player_id + 1,
+            players: state.players.len(),
+            map: self.map_name(),
+            max_turns: self.max_turns,
+            turn_timeout: self.turn_timeout,
+            time_bank: self.time_bank,
+            combat: self.combat,
+            victory: self.victory,
+            vision: self.vision,
+            capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+            planets: pw_serializer::map_planets(state),
+            distances: pw_serializer::distances(state),
+        }
+    }
+
+    pub fn create_game(&self, clients: usize) -> Result<PlanetWars, MapError> {
+        self.create_game_on(self.read_map()?, clients)
+    }
+
+    /// Creates a game on the given map instead of the map file, like a logged map.
+    /// The config has to pass `validate` first.
+    pub fn create_game_on(&self, map: Map, clients: usize) -> Result<PlanetWars, MapError> {
+        let planets = self.load_map(map, clients)?;
         let players = (0..clients)
             .map(|client_id| Player {
                 id: client_id,
                 alive: true,
+                eliminated: None,
             })
             .collect();
 
-        PlanetWars {
+        Ok(PlanetWars {
             players: players,
             planets: planets,
             expeditions: Vec::new(),
             expedition_num: 0,
             turn_num: 0,
             max_turns: self.max_turns,
-        }
+            combat: self.combat,
+            victory: self.victory,
+            vision: self.vision,
+        })
     }
 
-    fn load_map(&self, num_players: usize) -> Vec<Planet> {
-        let map = self.read_map().expect("[PLANET_WARS] reading map failed");
+    fn load_map(&self, map: Map, num_players: usize) -> Result<Vec<Planet>, MapError> {
+        map.validate()?;
+
+        if map.players() < num_players {
+            return Err(MapError::NotEnoughStartPlanets {
+                players: num_players,
+                start_planets: map.players(),
+            });
+        }
 
-        return map
+        return Ok(map
             .planets
             .into_iter()
             .enumerate()
@@ -65,16 +166,22 @@ impl Config {
                     x: planet.x,
                     y: planet.y,
                     fleets: fleets,
+                    growth: planet.growth,
+                    radius: planet.radius,
                 };
             })
-            .collect();
+            .collect());
     }
 
-    fn read_map(&self) -> io::Result<Map> {
-        let mut file = File::open(&self.map_file)?;
+    fn read_map(&self) -> Result<Map, MapError> {
+        let mut file = File::open(&self.map_file).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => MapError::MissingFile(self.map_file.clone()),
+            _ => MapError::Io(e.to_string()),
+        })?;
         let mut buf = String::new();
-        file.read_to_string(&mut buf)?;
-        let map = serde_json::from_str(&buf)?;
+        file.read_to_string(&mut buf)
+            .map_err(|e| MapError::Io(e.to_string()))?;
+        let map = serde_json::from_str(&buf).map_err(|e| MapError::InvalidJson(e.to_string()))?;
         return Ok(map);
     }
 }
@@ -83,3 +190,202 @@ impl Config {
 pub struct Map {
     pub planets: Vec<proto::Planet>,
 }
+
+impl Map {
+    /// Checks that the map can be played.
+    /// Player numbers on a map start at 1 and go up to the number of players,
+    /// without skipping any.
+    pub fn validate(&self) -> Result<(), MapError> {
+        if self.planets.is_empty() {
+            return Err(MapError::NoPlanets);
+        }
+
+        let players = self.players();
+        let mut names = HashSet::new();
+        for planet in self.planets.iter() {
+            if !names.insert(&planet.name) {
+                return Err(MapError::DuplicateName(planet.name.clone()));
+            }
+
+            if !planet.x.is_finite() || !planet.y.is_finite() {
+                return Err(MapError::InvalidCoordinates(planet.name.clone()));
+            }
+
+            match planet.owner {
+                Some(0) => return Err(MapError::OwnerZero(planet.name.clone())),
+                Some(owner) if owner > players => {
+                    return Err(MapError::OwnerOutOfRange {
+                        planet: planet.name.clone(),
+                        owner,
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The number of players with a start planet on this map.
+    pub fn players(&self) -> usize {
+        self.planets
+            .iter()
+            .filter_map(|p| p.owner)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+/// Why a map cannot be used.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type", content = "value")]
+pub enum MapError {
+    MissingFile(String),
+    Io(String),
+    InvalidJson(String),
+    NoPlanets,
+    DuplicateName(String),
+    InvalidCoordinates(String),
+    OwnerZero(String),
+    OwnerOutOfRange { planet: String, owner: usize },
+    NotEnoughStartPlanets { players: usize, start_planets: usize },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::MissingFile(file) => write!(f, "map file {} does not exist", file),
+            MapError::Io(e) => write!(f, "reading map failed: {}", e),
+            MapError::InvalidJson(e) => write!(f, "map is not valid json: {}", e),
+            MapError::NoPlanets => write!(f, "map has no planets"),
+            MapError::DuplicateName(name) => write!(f, "planet name {} is used twice", name),
+            MapError::InvalidCoordinates(name) => {
+                write!(f, "planet {} has invalid coordinates", name)
+            }
+            MapError::OwnerZero(name) => {
+                write!(f, "planet {} has owner 0, players start at 1", name)
+            }
+            MapError::OwnerOutOfRange { planet, owner } => write!(
+                f,
+                "planet {} has owner {}, but the map has fewer players",
+                planet, owner
+            ),
+            MapError::NotEnoughStartPlanets {
+                players,
+                start_planets,
+            } => write!(
+                f,
+                "{} players need a start planet, the map only has {}",
+                players, start_planets
+            ),
+        }
+    }
+}
+
+/// Why the rules of a config cannot be played, on any map.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type", content = "value")]
+pub enum ConfigError {
+    /// The domination fraction is not above 0 and at most 1.
+    InvalidDomination(f64),
+    /// The vision radius is not above 0.
+    InvalidVision(f64),
+    TurnTimeoutTooLong(u64),
+    TimeBankTooLarge(u64),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::InvalidDomination(fraction) => write!(
+                f,
+                "domination needs a fraction above 0 and at most 1, not {}",
+                fraction
+            ),
+            ConfigError::InvalidVision(radius) => {
+                write!(f, "the vision radius has to be above 0, not {}", radius)
+            }
+            ConfigError::TurnTimeoutTooLong(timeout) => write!(
+                f,
+                "the turn timeout can be at most {} ms, not {}",
+                MAX_TURN_TIMEOUT, timeout
+            ),
+            ConfigError::TimeBankTooLarge(bank) => write!(
+                f,
+                "the time bank can be at most {} ms, not {}",
+                MAX_TIME_BANK, bank
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            map_file: "maps/hex.json".to_string(),
+            max_turns: 100,
+            combat: CombatRule::default(),
+            victory: Victory::default(),
+            vision: None,
+            turn_timeout: default_turn_timeout(),
+            time_bank: None,
+            disqualify: None,
+        }
+    }
+
+    #[test]
+    fn rules_and_times_are_validated() {
+        assert_eq!(config().validate(), Ok(()));
+        let limits = Config {
+            victory: Victory::Domination(1.0),
+            vision: Some(0.5),
+            turn_timeout: MAX_TURN_TIMEOUT,
+            time_bank: Some(MAX_TIME_BANK),
+            ..config()
+        };
+        assert_eq!(limits.validate(), Ok(()));
+
+        for &fraction in [0.0, 1.5, f64::NAN].iter() {
+            let config = Config {
+                victory: Victory::Domination(fraction),
+                ..config()
+            };
+            assert!(matches!(
+                config.validate(),
+                Err(ConfigError::InvalidDomination(_))
+            ));
+        }
+        for &radius in [0.0, -3.0, f64::NAN].iter() {
+            let config = Config {
+                vision: Some(radius),
+                ..config()
+            };
+            assert!(matches!(
+                config.validate(),
+                Err(ConfigError::InvalidVision(_))
+            ));
+        }
+        let config = Config {
+            turn_timeout: MAX_TURN_TIMEOUT + 1,
+            time_bank: Some(MAX_TIME_BANK + 1),
+            ..config()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::TurnTimeoutTooLong(MAX_TURN_TIMEOUT + 1))
+        );
+        let config = Config {
+            turn_timeout: 1,
+            ..config
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::TimeBankTooLarge(MAX_TIME_BANK + 1))
+        );
+    }
+}
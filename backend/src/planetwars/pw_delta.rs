@@ -0,0 +1,377 @@
+//! A compact format for game logs.
+//!
+//! The first line holds the map, every following line only holds what changed in a turn:
+//! planets with a new owner or ship count, new expeditions and the expeditions that arrived.
+//! Expeditions that just got a turn closer to their destination are left out.
+//! Planets are referred to by their index in the header, instead of their name.
+//! The turns are decoded by `pw_protocol::delta`, which the visualizer uses as well.
+//!
+//! Compact logs can be gzip compressed as a whole, readers recognise both.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use super::pw_log::{LogHeader, LogTurn, LoggedAction};
+use super::pw_protocol as proto;
+use super::pw_protocol::delta::{advance, Decoder, DeltaExpedition, FORMAT};
+
+/// Every line after the header: how a turn changed the state.
+pub type TurnDelta = proto::delta::TurnDelta<LoggedAction>;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The first line of a compact log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeltaHeader {
+    pub format: String,
+    /// The header of the full log, logs of old games have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<LogHeader>,
+    /// The planets as they are at the start of the game.
+    pub planets: Vec<proto::Planet>,
+}
+
+/// Turns the lines of a full log into the lines of a compact one.
+pub fn compact<I>(lines: I) -> io::Result<Vec<String>>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut lines = lines.into_iter().filter(|line| !line.trim().is_empty());
+
+    let first = lines.next().ok_or_else(|| invalid("the log is empty"))?;
+    let (log, first_turn) = match serde_json::from_str::<LogHeader>(&first) {
+        Ok(header) => (Some(header), lines.next()),
+        Err(_) => (None, Some(first)),
+    };
+    let first_turn: LogTurn = parse(&first_turn.ok_or_else(|| invalid("the log has no turns"))?)?;
+
+    let header = DeltaHeader {
+        format: FORMAT.to_string(),
+        log,
+        planets: first_turn.state.planets.clone(),
+    };
+    let mut encoder = Encoder::new(&header.planets);
+    let mut compacted = vec![to_line(&header)];
+
+    compacted.push(to_line(&encoder.encode(first_turn)?));
+    for line in lines {
+        compacted.push(to_line(&encoder.encode(parse(&line)?)?));
+    }
+
+    Ok(compacted)
+}
+
+/// Turns the lines of a compact log back into the lines of the full log.
+pub fn expand<I>(lines: I) -> io::Result<Vec<String>>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut lines = lines.into_iter().filter(|line| !line.trim().is_empty());
+
+    let header: DeltaHeader = parse(&lines.next().ok_or_else(|| invalid("the log is empty"))?)?;
+    let mut decoder = Decoder::new(&header.planets);
+    let mut expanded: Vec<String> = header.log.iter().map(to_line).collect();
+
+    for line in lines {
+        let turn = decoder
+            .decode::<LoggedAction>(parse(&line)?)
+            .map_err(invalid)?;
+        expanded.push(to_line(&LogTurn {
+            state: turn.state,
+            actions: turn.actions,
+            visible: turn.visible,
+        }));
+    }
+
+    Ok(expanded)
+}
+
+/// Reads the lines of a log in any format, as the lines of a full log.
+pub fn read_log<P: AsRef<Path>>(path: P) -> io::Result<Vec<String>> {
+    let mut content = Vec::new();
+    File::open(path)?.read_to_end(&mut content)?;
+    decode_log(&content)
+}
+
+/// The lines of a full log, from the content of a log file in any format.
+pub fn decode_log(content: &[u8]) -> io::Result<Vec<String>> {
+    let lines = if content.starts_with(&GZIP_MAGIC) {
+        BufReader::new(GzDecoder::new(content))
+            .lines()
+            .collect::<io::Result<Vec<_>>>()?
+    } else {
+        content.lines().collect::<io::Result<Vec<_>>>()?
+    };
+
+    match lines.first().map(|line| serde_json::from_str::<DeltaHeader>(line)) {
+        Some(Ok(header)) if header.format == FORMAT => expand(lines),
+        _ => Ok(lines),
+    }
+}
+
+/// Writes the lines of a compact log, gzip compressed or not.
+/// The lines are written to a temporary file first, so a log can be compacted in place.
+pub fn write_log<P: AsRef<Path>>(path: P, lines: &[String], gzip: bool) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp = path.with_extension("tmp");
+    let file = File::create(&tmp)?;
+
+    if gzip {
+        let mut encoder = GzEncoder::new(file, Compression::best());
+        write_lines(&mut encoder, lines)?;
+        encoder.finish()?;
+    } else {
+        write_lines(&mut io::BufWriter::new(file), lines)?;
+    }
+
+    fs::rename(&tmp, path)
+}
+
+fn write_lines<W: Write>(out: &mut W, lines: &[String]) -> io::Result<()> {
+    for line in lines {
+        write!(out, "{}\n", line)?;
+    }
+    out.flush()
+}
+
+/// Keeps the previous state, to tell what changed in a turn.
+struct Encoder {
+    indices: HashMap<String, usize>,
+    state: proto::State,
+}
+
+impl Encoder {
+    fn new(planets: &[proto::Planet]) -> Self {
+        Encoder {
+            indices: planets
+                .iter()
+                .enumerate()
+                .map(|(i, p)| (p.name.clone(), i))
+                .collect(),
+            state: proto::State {
+                planets: planets.to_vec(),
+                expeditions: Vec::new(),
+                time: None,
+            },
+        }
+    }
+
+    fn encode(&mut self, turn: LogTurn) -> io::Result<TurnDelta> {
+        if turn.state.planets.len() != self.state.planets.len() {
+            return Err(invalid("the number of planets changed"));
+        }
+
+        let planets = self
+            .state
+            .planets
+            .iter()
+            .zip(turn.state.planets.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old.owner != new.owner || old.ship_count != new.ship_count)
+            .map(|(i, (_, new))| (i, new.owner, new.ship_count))
+            .collect();
+
+        let old_ids: HashSet<u64> = ids_of(&self.state).collect();
+        let new_ids: HashSet<u64> = ids_of(&turn.state).collect();
+        let arrived: Vec<u64> = ids_of(&self.state)
+            .filter(|id| !new_ids.contains(id))
+            .collect();
+
+        // Expeditions are dispatched in the order of their ids
+        let mut dispatched: Vec<&proto::Expedition> = turn
+            .state
+            .expeditions
+            .iter()
+            .filter(|e| !old_ids.contains(&e.id))
+            .collect();
+        dispatched.sort_by_key(|e| e.id);
+
+        let mut expected = advance(
+            &self.state.expeditions,
+            dispatched.iter().map(|&e| e.clone()).collect(),
+            &arrived,
+        );
+        let mut expeditions = dispatched
+            .iter()
+            .map(|e| self.compact_expedition(e))
+            .collect::<io::Result<Vec<_>>>()?;
+        for exp in turn.state.expeditions.iter() {
+            if let Some(old) = expected.iter_mut().find(|e| e.id == exp.id && *e != exp) {
+                *old = exp.clone();
+                expeditions.push(self.compact_expedition(exp)?);
+            }
+        }
+
+        let order = if expected.iter().map(|e| e.id).eq(ids_of(&turn.state)) {
+            None
+        } else {
+            Some(ids_of(&turn.state).collect())
+        };
+
+        let visible = turn
+            .visible
+            .iter()
+            .map(|v| {
+                let planets = v
+                    .planets
+                    .iter()
+                    .map(|name| self.index(name))
+                    .collect::<io::Result<_>>()?;
+                Ok((planets, v.expeditions.clone()))
+            })
+            .collect::<io::Result<_>>()?;
+
+        self.state = turn.state;
+        Ok(TurnDelta {
+            planets,
+            expeditions,
+            arrived,
+            order,
+            actions: turn.actions,
+            visible,
+        })
+    }
+
+    fn compact_expedition(&self, exp: &proto::Expedition) -> io::Result<DeltaExpedition> {
+        Ok((
+            exp.id,
+            self.endpoint(&exp.origin)?,
+            self.endpoint(&exp.destination)?,
+            exp.owner,
+            exp.ship_count,
+            exp.turns_remaining,
+        ))
+    }
+
+    /// Logs hold full states, the planets of expeditions are never hidden there.
+    fn endpoint(&self, name: &Option<String>) -> io::Result<usize> {
+        match name {
+            Some(name) => self.index(name),
+            None => Err(invalid("expedition without origin or destination")),
+        }
+    }
+
+    fn index(&self, name: &str) -> io::Result<usize> {
+        self.indices
+            .get(name)
+            .cloned()
+            .ok_or_else(|| invalid(format!("unknown planet {}", name)))
+    }
+}
+
+fn ids_of(state: &proto::State) -> impl Iterator<Item = u64> + '_ {
+    state.expeditions.iter().map(|e| e.id)
+}
+
+fn parse<T: DeserializeOwned>(line: &str) -> io::Result<T> {
+    serde_json::from_str(line).map_err(|e| invalid(e.to_string()))
+}
+
+fn to_line<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(reason: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use planetwars_rules::Simulation;
+
+    use super::super::pw_serializer;
+
+    fn planet(name: &str, x: f64, owner: Option<usize>, ship_count: u64) -> proto::Planet {
+        proto::Planet {
+            ship_count,
+            x,
+            y: 0.0,
+            owner,
+            name: name.to_string(),
+            growth: 1,
+            radius: None,
+        }
+    }
+
+    fn command(origin: &str, destination: &str, ship_count: u64) -> proto::Command {
+        proto::Command {
+            origin: origin.into(),
+            destination: destination.into(),
+            ship_count,
+        }
+    }
+
+    /// The lines of a full log of a short game, with expeditions arriving out of order.
+    fn log() -> Vec<String> {
+        let mut sim = Simulation::from_state(&proto::State {
+            planets: vec![
+                planet("a", 0.0, Some(1), 30),
+                planet("b", 2.0, None, 2),
+                planet("c", 5.0, None, 4),
+                planet("d", 9.0, Some(2), 30),
+            ],
+            expeditions: Vec::new(),
+            time: None,
+        });
+        let turns = vec![
+            vec![
+                (0, vec![command("a", "d", 5), command("a", "b", 3)]),
+                (1, vec![command("d", "c", 6)]),
+            ],
+            vec![(0, vec![command("a", "c", 4)]), (1, Vec::new())],
+            vec![(0, Vec::new()), (1, vec![command("d", "a", 2)])],
+        ];
+
+        let mut lines = Vec::new();
+        for turn in turns.into_iter().chain((0..10).map(|_| Vec::new())) {
+            sim.turn(turn);
+            lines.push(to_line(&LogTurn {
+                state: pw_serializer::serialize(sim.state()),
+                actions: Vec::new(),
+                visible: vec![proto::Visibility {
+                    planets: vec!["a".to_string(), "c".to_string()],
+                    expeditions: sim.state().expeditions.iter().map(|e| e.id).collect(),
+                }],
+            }));
+        }
+        lines
+    }
+
+    #[test]
+    fn expanding_a_compacted_log_gives_the_log_back() {
+        let lines = log();
+        let compacted = compact(lines.clone()).unwrap();
+        assert_eq!(compacted.len(), lines.len() + 1);
+        assert_eq!(expand(compacted).unwrap(), lines);
+    }
+
+    #[test]
+    fn expeditions_getting_closer_are_left_out() {
+        let compacted = compact(log()).unwrap();
+        let deltas: Vec<TurnDelta> = compacted[1..].iter().map(|l| parse(l).unwrap()).collect();
+
+        assert_eq!(deltas[0].expeditions.len(), 3);
+        assert!(deltas[4..].iter().all(|d| d.expeditions.is_empty()));
+        assert!(deltas.iter().all(|d| d.order.is_none()));
+    }
+
+    #[test]
+    fn a_delta_with_an_unknown_planet_is_refused() {
+        let header = to_line(&DeltaHeader {
+            format: FORMAT.to_string(),
+            log: None,
+            planets: vec![planet("a", 0.0, Some(1), 30)],
+        });
+        let turn = r#"{"planets": [[3, 1, 10]]}"#.to_string();
+        assert!(expand(vec![header, turn]).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The logged turns of a running game, as they are played.
+/// A spectator gets every turn so far, followed by every new turn.
+#[derive(Clone, Default)]
+pub struct Feed {
+    inner: Arc<Mutex<FeedInner>>,
+}
+
+#[derive(Default)]
+struct FeedInner {
+    turns: Vec<String>,
+    spectators: Vec<UnboundedSender<String>>,
+    closed: bool,
+}
+
+impl Feed {
+    /// Sends a turn to all spectators, forgetting the ones that left.
+    pub fn publish(&self, turn: String) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .spectators
+            .retain(|spectator| spectator.unbounded_send(turn.clone()).is_ok());
+        inner.turns.push(turn);
+    }
+
+    /// Ends the feed when the game is over, the streams of all spectators end after the last turn.
+    pub fn close(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.closed = true;
+        inner.turns = Vec::new();
+        inner.spectators.clear();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.lock().unwrap().closed
+    }
+
+    /// A stream of all turns of the game, `None` when the game is already over.
+    pub fn subscribe(&self) -> Option<UnboundedReceiver<String>> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed {
+            return None;
+        }
+
+        let (tx, rx) = mpsc::unbounded();
+        for turn in inner.turns.iter() {
+            tx.unbounded_send(turn.clone()).unwrap();
+        }
+        inner.spectators.push(tx);
+
+        Some(rx)
+    }
+}
+
+/// The feeds of all running games, by game id.
+#[derive(Default)]
+pub struct Feeds {
+    feeds: Mutex<HashMap<String, Feed>>,
+}
+
+impl Feeds {
+    /// Creates the feed of a new game, forgetting the feeds of games that are over.
+    pub fn create(&self, game_id: &str) -> Feed {
+        let mut feeds = self.feeds.lock().unwrap();
+        feeds.retain(|_, feed| !feed.is_closed());
+
+        let feed = Feed::default();
+        feeds.insert(game_id.to_string(), feed.clone());
+        feed
+    }
+
+    /// A stream of all turns of a running game.
+    pub fn subscribe(&self, game_id: &str) -> Option<UnboundedReceiver<String>> {
+        self.feeds.lock().unwrap().get(game_id)?.subscribe()
+    }
+}
@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::Write;
+
+use serde_json;
+
+use super::pw_config::Config;
+use super::pw_protocol as proto;
+use super::pw_rules::PlanetWars;
+use super::pw_serializer;
+
+/// The first line of a game log, holding everything needed to replay the game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogHeader {
+    pub config: Config,
+    pub players: usize,
+    /// The capabilities every player asked for, by player id.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<Vec<String>>,
+    /// The planets at the start of the game, so the game can be replayed without the map file.
+    /// Logs of old games have none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub planets: Vec<proto::Planet>,
+}
+
+impl LogHeader {
+    /// The header of a game that is about to start in the given state.
+    pub fn new(config: &Config, state: &PlanetWars, capabilities: Vec<Vec<String>>) -> Self {
+        LogHeader {
+            config: config.clone(),
+            players: state.players.len(),
+            capabilities,
+            planets: pw_serializer::serialize(state).planets,
+        }
+    }
+}
+
+/// The action of one player in a turn.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedAction {
+    /// Player number, 1-based just like in the logged states.
+    pub player: usize,
+    pub action: proto::PlayerAction,
+    /// What was left of the player's time bank after the turn, in games with a time bank.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bank: Option<u64>,
+    /// The debug notes the player sent with its turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug: Option<proto::Debug>,
+}
+
+/// Every line after the header: the state after a turn,
+/// together with the actions of that turn in the order they were executed.
+/// The state is flattened so the visualizer can still read these lines as a state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogTurn {
+    #[serde(flatten)]
+    pub state: proto::State,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<LoggedAction>,
+    /// With fog of war, what every player could see, ordered by player number.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub visible: Vec<proto::Visibility>,
+}
+
+/// Writes a game log, one json object per line.
+pub struct GameLog {
+    file: File,
+}
+
+impl GameLog {
+    pub fn new(file: File) -> Self {
+        GameLog { file }
+    }
+
+    /// Writes the first line, before any turn.
+    pub fn write_header(&mut self, header: &LogHeader) {
+        write!(self.file, "{}\n", serde_json::to_string(header).unwrap()).unwrap();
+    }
+
+    /// Writes the state after a turn, returning the logged line.
+    pub fn log_turn(&mut self, state: &PlanetWars, actions: Vec<LoggedAction>) -> String {
+        let turn = LogTurn {
+            state: pw_serializer::serialize(state),
+            actions,
+            visible: state
+                .players
+                .iter()
+                .filter_map(|p| pw_serializer::visibility(state, p.id))
+                .collect(),
+        };
+        let line = serde_json::to_string(&turn).unwrap();
+        write!(self.file, "{}\n", line).unwrap();
+        line
+    }
+}
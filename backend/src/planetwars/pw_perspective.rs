@@ -0,0 +1,233 @@
+//! A game log as one player saw it.
+//!
+//! Every message the player received is rebuilt from the logged states,
+//! so a student can step through what their bot saw and what became of its commands.
+//! The rules are not replayed, so this works for games that are still running or were stopped,
+//! and does not need the map file.
+
+use planetwars_rules::Simulation;
+use serde_json;
+
+use super::pw_log::{LogHeader, LogTurn, LoggedAction};
+use super::pw_protocol as proto;
+use super::pw_replay::{self, ReplayError};
+use super::pw_rules::{PlanetWars, Player};
+use super::pw_serializer;
+
+/// The first line of a perspective log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PerspectiveHeader {
+    #[serde(flatten)]
+    pub log: LogHeader,
+    /// Player number, 1-based just like in the logged states.
+    pub player: usize,
+}
+
+/// Every line after the header: the whole state after a turn, rotated for the player,
+/// with the messages the player received that turn.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PerspectiveTurn {
+    #[serde(flatten)]
+    pub state: proto::State,
+    /// The action of the player this turn, with its debug notes, as player 1.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<LoggedAction>,
+    /// With fog of war, what every player could see, ordered by rotated player number.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub visible: Vec<proto::Visibility>,
+    /// The messages exactly as they were sent, in order.
+    /// Empty once the player is out of the game.
+    pub messages: Vec<String>,
+}
+
+/// Turns the lines of a full log into the lines of a perspective log for the given player.
+/// A log that ends early, with a last line that is cut off, gives the turns up to there.
+pub fn perspective<I>(lines: I, player: usize) -> Result<Vec<String>, ReplayError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut lines = lines
+        .into_iter()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .peekable();
+
+    let header: LogHeader = pw_replay::parse_line(lines.next())?;
+    let player_id = player.wrapping_sub(1);
+    if player_id >= header.players {
+        return Err(ReplayError::InvalidLog {
+            line: 1,
+            reason: format!("the game has no player {}", player),
+        });
+    }
+
+    let mut perspective = vec![serde_json::to_string(&PerspectiveHeader {
+        log: header.clone(),
+        player,
+    })
+    .unwrap()];
+    let mut were_alive = None;
+    let mut bank = header.config.time_bank;
+
+    while let Some((num, line)) = lines.next() {
+        let turn: LogTurn = match serde_json::from_str(&line) {
+            Ok(turn) => turn,
+            // the server stopped while writing the last turn
+            Err(_) if lines.peek().is_none() => break,
+            Err(e) => {
+                return Err(ReplayError::InvalidLog {
+                    line: num + 1,
+                    reason: e.to_string(),
+                })
+            }
+        };
+        let state = game(&header, &turn.state, perspective.len() as u64 - 1);
+
+        let mut actions = Vec::new();
+        let mut messages = Vec::new();
+        if perspective.len() == 1 {
+            let welcome = proto::ServerMessage::Welcome(header.config.game_info(&state, player_id));
+            messages.push(serde_json::to_string(&welcome).unwrap());
+        }
+        for logged in turn.actions.iter().filter(|a| a.player == player) {
+            bank = logged.bank.or(bank);
+            let message = proto::ServerMessage::PlayerAction(logged.action.clone());
+            messages.push(serde_json::to_string(&message).unwrap());
+            actions.push(LoggedAction {
+                player: 1,
+                ..logged.clone()
+            });
+        }
+
+        let was_alive = were_alive.map_or(true, |alive: Vec<usize>| alive.contains(&player_id));
+        if was_alive {
+            let time = proto::TimeLeft {
+                turn: header.config.turn_timeout,
+                bank,
+            };
+            let capabilities = header.capabilities.get(player_id).map(Vec::as_slice);
+            let message =
+                super::state_message(&state, player_id, time, capabilities.unwrap_or_default());
+            messages.push(serde_json::to_string(&message).unwrap());
+        }
+        were_alive = Some(state.living_players());
+
+        let players = state.players.len();
+        let turn = PerspectiveTurn {
+            state: pw_serializer::serialize_rotated(&state, player_id),
+            actions,
+            visible: (0..players)
+                .filter_map(|i| pw_serializer::visibility(&state, (player_id + i) % players))
+                .collect(),
+            messages,
+        };
+        perspective.push(serde_json::to_string(&turn).unwrap());
+    }
+
+    Ok(perspective)
+}
+
+/// The game in a logged state, with the players and rules of the log header.
+/// Players that own nothing anymore are out of the game, just like in the rules.
+fn game(header: &LogHeader, logged: &proto::State, turn_num: u64) -> PlanetWars {
+    let mut state = Simulation::from_state(logged)
+        .with_combat(header.config.combat)
+        .with_victory(header.config.victory)
+        .state()
+        .clone();
+
+    for id in state.players.len()..header.players {
+        state.players.push(Player {
+            id,
+            alive: false,
+            eliminated: None,
+        });
+    }
+    state.turn_num = turn_num;
+    state.max_turns = header.config.max_turns;
+    state.vision = header.config.vision;
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Config;
+    use super::*;
+
+    fn planet(name: &str, x: f64, owner: Option<usize>) -> proto::Planet {
+        proto::Planet {
+            ship_count: 10,
+            x,
+            y: 0.0,
+            owner,
+            name: name.to_string(),
+            growth: 1,
+            radius: None,
+        }
+    }
+
+    /// A log of a game on a map that no longer exists, stopped after a few turns.
+    fn log() -> Vec<String> {
+        let header = LogHeader {
+            config: Config {
+                map_file: "maps/moved.json".to_string(),
+                max_turns: 100,
+                combat: Default::default(),
+                victory: Default::default(),
+                vision: None,
+                turn_timeout: 1000,
+                time_bank: None,
+                disqualify: None,
+            },
+            players: 2,
+            capabilities: Vec::new(),
+            planets: Vec::new(),
+        };
+        let mut sim = Simulation::from_state(&proto::State {
+            planets: vec![planet("a", 0.0, Some(1)), planet("b", 5.0, Some(2))],
+            expeditions: Vec::new(),
+            time: None,
+        });
+
+        let mut lines = vec![serde_json::to_string(&header).unwrap()];
+        for _ in 0..4 {
+            lines.push(
+                serde_json::to_string(&LogTurn {
+                    state: pw_serializer::serialize(sim.state()),
+                    actions: Vec::new(),
+                    visible: Vec::new(),
+                })
+                .unwrap(),
+            );
+            sim.turn(Vec::new());
+        }
+        lines
+    }
+
+    #[test]
+    fn unfinished_games_are_shown_without_their_map() {
+        let perspective = perspective(log(), 2).unwrap();
+        assert_eq!(perspective.len(), 5);
+
+        let first: PerspectiveTurn = serde_json::from_str(&perspective[1]).unwrap();
+        assert_eq!(first.state.planets[1].owner, Some(1));
+        assert!(first.messages[0].contains("welcome"));
+    }
+
+    #[test]
+    fn a_cut_off_last_line_is_left_out() {
+        let mut lines = log();
+        let last = lines.pop().unwrap();
+        lines.push(last[..last.len() / 2].to_string());
+        assert_eq!(perspective(lines.clone(), 1).unwrap().len(), 4);
+
+        lines.push(lines[1].clone());
+        assert!(perspective(lines, 1).is_err());
+    }
+
+    #[test]
+    fn unknown_players_are_refused() {
+        assert!(perspective(log(), 0).is_err());
+        assert!(perspective(log(), 3).is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use super::pw_config::Map;
+use super::pw_log::{LogHeader, LogTurn};
+use super::pw_protocol as proto;
+use super::pw_rules::PlanetWars;
+use super::pw_serializer;
+use super::pw_stats::PlayerStats;
+
+/// Why a replayed game log is not consistent with the rules.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The log itself could not be read.
+    InvalidLog { line: usize, reason: String },
+    /// Replaying the logged actions gives a different game from this turn on.
+    Diverged { turn: u64, reason: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::InvalidLog { line, reason } => {
+                write!(f, "invalid log at line {}: {}", line, reason)
+            }
+            ReplayError::Diverged { turn, reason } => {
+                write!(f, "diverged at turn {}: {}", turn, reason)
+            }
+        }
+    }
+}
+
+/// Replays a game from the lines of its log, starting from the map in the header.
+/// Logs of old games have no planets in the header, their map is read from the map file.
+/// Returns the number of turns that were verified.
+pub fn replay<I>(lines: I) -> Result<u64, ReplayError>
+where
+    I: IntoIterator<Item = String>,
+{
+    replay_with(lines, |_, _, _| ())
+}
+
+/// Replays a game like `replay`, calling `on_turn` with the replayed state
+/// and the logged turn, for the initial state and after every turn.
+pub fn replay_with<I, F>(lines: I, mut on_turn: F) -> Result<u64, ReplayError>
+where
+    I: IntoIterator<Item = String>,
+    F: FnMut(&LogHeader, &PlanetWars, &LogTurn),
+{
+    let mut lines = lines
+        .into_iter()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let header: LogHeader = parse_line(lines.next())?;
+    let created = if header.planets.is_empty() {
+        header.config.create_game(header.players)
+    } else {
+        let map = Map {
+            planets: header.planets.clone(),
+        };
+        header.config.create_game_on(map, header.players)
+    };
+    let mut state = created.map_err(|e| ReplayError::InvalidLog {
+        line: 1,
+        reason: e.to_string(),
+    })?;
+    let planet_map = super::planet_map(&state);
+    let mut stats = vec![PlayerStats::default(); header.players];
+
+    let initial: LogTurn = parse_line(lines.next())?;
+    compare_state(&state, &initial.state)?;
+    on_turn(&header, &state, &initial);
+
+    for line in lines {
+        let line_num = line.0 + 1;
+        let turn: LogTurn = parse_line(Some(line))?;
+
+        if state.is_finished() {
+            return Err(ReplayError::Diverged {
+                turn: state.turn_num + 1,
+                reason: "the game was already finished".to_string(),
+            });
+        }
+
+        state.repopulate();
+        for logged in turn.actions.iter() {
+            if logged.player == 0 || logged.player > state.players.len() {
+                return Err(ReplayError::InvalidLog {
+                    line: line_num,
+                    reason: format!("unknown player {}", logged.player),
+                });
+            }
+
+            stats[logged.player - 1].record(&logged.action);
+
+            if let proto::PlayerAction::Commands(commands) = &logged.action {
+                let results = super::execute_commands(
+                    &mut state,
+                    &planet_map,
+                    logged.player - 1,
+                    commands.iter().map(|c| c.command.clone()).collect(),
+                );
+
+                for (expected, result) in commands.iter().zip(results.iter()) {
+                    if expected.error != result.error {
+                        return Err(ReplayError::Diverged {
+                            turn: state.turn_num + 1,
+                            reason: format!(
+                                "command {:?} of player {} was logged with error {:?}, replayed with {:?}",
+                                expected.command, logged.player, expected.error, result.error
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+        state.step();
+        if let Some(policy) = &header.config.disqualify {
+            super::disqualify_players(&mut state, policy, &mut stats);
+        }
+
+        compare_state(&state, &turn.state)?;
+        on_turn(&header, &state, &turn);
+    }
+
+    if !state.is_finished() {
+        return Err(ReplayError::Diverged {
+            turn: state.turn_num,
+            reason: "the log ends before the game is finished".to_string(),
+        });
+    }
+
+    Ok(state.turn_num)
+}
+
+pub(super) fn parse_line<T: DeserializeOwned>(
+    line: Option<(usize, String)>,
+) -> Result<T, ReplayError> {
+    let (num, line) = line.ok_or_else(|| ReplayError::InvalidLog {
+        line: 0,
+        reason: "unexpected end of log".to_string(),
+    })?;
+
+    serde_json::from_str(&line).map_err(|e| ReplayError::InvalidLog {
+        line: num + 1,
+        reason: e.to_string(),
+    })
+}
+
+fn compare_state(state: &PlanetWars, logged: &proto::State) -> Result<(), ReplayError> {
+    let diverged = |reason: String| ReplayError::Diverged {
+        turn: state.turn_num,
+        reason,
+    };
+    let replayed = pw_serializer::serialize(state);
+
+    if replayed.planets.len() != logged.planets.len() {
+        return Err(diverged(format!(
+            "{} planets were logged, replay has {}",
+            logged.planets.len(),
+            replayed.planets.len()
+        )));
+    }
+
+    for (logged, replayed) in logged.planets.iter().zip(replayed.planets.iter()) {
+        if logged != replayed {
+            return Err(diverged(format!(
+                "planet {:?} was logged, replay has {:?}",
+                logged, replayed
+            )));
+        }
+    }
+
+    if replayed.expeditions.len() != logged.expeditions.len() {
+        return Err(diverged(format!(
+            "{} expeditions were logged, replay has {}",
+            logged.expeditions.len(),
+            replayed.expeditions.len()
+        )));
+    }
+
+    for (logged, replayed) in logged.expeditions.iter().zip(replayed.expeditions.iter()) {
+        if logged != replayed {
+            return Err(diverged(format!(
+                "expedition {:?} was logged, replay has {:?}",
+                logged, replayed
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::pw_log::LoggedAction;
+    use super::super::Config;
+    use super::*;
+
+    fn planet(name: &str, x: f64, owner: Option<usize>, ship_count: u64) -> proto::Planet {
+        proto::Planet {
+            ship_count,
+            x,
+            y: 0.0,
+            owner,
+            name: name.to_string(),
+            growth: 1,
+            radius: None,
+        }
+    }
+
+    fn command(origin: &str, destination: &str, ship_count: u64) -> proto::Command {
+        proto::Command {
+            origin: origin.into(),
+            destination: destination.into(),
+            ship_count,
+        }
+    }
+
+    /// The log of a 6 turn game on a map that only exists in the header.
+    fn log() -> Vec<String> {
+        let config = Config {
+            map_file: "maps/moved.json".to_string(),
+            max_turns: 6,
+            combat: Default::default(),
+            victory: Default::default(),
+            vision: None,
+            turn_timeout: 1000,
+            time_bank: None,
+            disqualify: None,
+        };
+        let map = Map {
+            planets: vec![
+                planet("a", 0.0, Some(1), 10),
+                planet("b", 2.0, None, 3),
+                planet("c", 4.0, Some(2), 10),
+            ],
+        };
+        let mut state = config.create_game_on(map, 2).unwrap();
+        let planet_map = super::super::planet_map(&state);
+
+        let to_line = |state: &PlanetWars, actions: Vec<LoggedAction>| {
+            serde_json::to_string(&LogTurn {
+                state: pw_serializer::serialize(state),
+                actions,
+                visible: Vec::new(),
+            })
+            .unwrap()
+        };
+        let mut lines = vec![
+            serde_json::to_string(&LogHeader::new(&config, &state, Vec::new())).unwrap(),
+            to_line(&state, Vec::new()),
+        ];
+
+        let turns = vec![
+            vec![command("a", "b", 5), command("a", "c", 50)],
+            vec![command("a", "c", 3)],
+        ];
+        for commands in turns.into_iter().chain((0..4).map(|_| Vec::new())) {
+            state.repopulate();
+            let results = super::super::execute_commands(&mut state, &planet_map, 0, commands);
+            state.step();
+            let action = LoggedAction {
+                player: 1,
+                action: proto::PlayerAction::Commands(results),
+                bank: None,
+                debug: None,
+            };
+            lines.push(to_line(&state, vec![action]));
+        }
+        lines
+    }
+
+    /// Changes a line of the log as json.
+    fn edit<F: FnOnce(&mut serde_json::Value)>(lines: &mut [String], line: usize, f: F) {
+        let mut value: serde_json::Value = serde_json::from_str(&lines[line]).unwrap();
+        f(&mut value);
+        lines[line] = value.to_string();
+    }
+
+    fn diverged_at(result: Result<u64, ReplayError>) -> Option<u64> {
+        match result {
+            Err(ReplayError::Diverged { turn, .. }) => Some(turn),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn a_consistent_log_replays_without_the_map_file() {
+        assert_eq!(replay(log()).unwrap(), 6);
+    }
+
+    #[test]
+    fn a_changed_state_is_found() {
+        let mut lines = log();
+        edit(&mut lines, 3, |turn| {
+            turn["planets"][1]["ship_count"] = 1.into()
+        });
+        assert_eq!(diverged_at(replay(lines)), Some(2));
+    }
+
+    #[test]
+    fn a_changed_command_result_is_found() {
+        let mut lines = log();
+        edit(&mut lines, 2, |turn| {
+            turn["actions"][0]["action"]["value"][1]["error"] = serde_json::Value::Null
+        });
+        assert_eq!(diverged_at(replay(lines)), Some(1));
+    }
+
+    #[test]
+    fn a_different_start_is_found() {
+        let mut lines = log();
+        edit(&mut lines, 0, |header| {
+            header["planets"][1]["ship_count"] = 4.into()
+        });
+        assert_eq!(diverged_at(replay(lines)), Some(0));
+    }
+
+    #[test]
+    fn logs_must_end_with_the_end_of_the_game() {
+        let mut lines = log();
+        lines.truncate(5);
+        assert_eq!(diverged_at(replay(lines.clone())), Some(3));
+
+        let mut lines = log();
+        let last = lines.last().unwrap().clone();
+        lines.push(last);
+        assert_eq!(diverged_at(replay(lines)), Some(7));
+    }
+
+    #[test]
+    fn actions_of_unknown_players_are_invalid() {
+        let mut lines = log();
+        edit(&mut lines, 2, |turn| {
+            turn["actions"][0]["player"] = 3.into()
+        });
+        match replay(lines) {
+            Err(ReplayError::InvalidLog { line, .. }) => assert_eq!(line, 3),
+            other => panic!("{:?}", other),
+        }
+    }
+}
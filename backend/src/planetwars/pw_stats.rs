@@ -0,0 +1,237 @@
+use super::pw_protocol::{self as proto, CommandError};
+
+/// How often a player misbehaved during a game, and whether it was disqualified for it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerStats {
+    pub timeouts: u64,
+    /// Longest run of timeouts in a row.
+    pub max_consecutive_timeouts: u64,
+    pub parse_errors: u64,
+    pub not_enough_ships: u64,
+    pub origin_not_owned: u64,
+    pub zero_ship_move: u64,
+    pub origin_does_not_exist: u64,
+    pub destination_does_not_exist: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disqualified: Option<Disqualification>,
+    #[serde(skip)]
+    consecutive_timeouts: u64,
+}
+
+impl PlayerStats {
+    pub fn record(&mut self, action: &proto::PlayerAction) {
+        if let proto::PlayerAction::Timeout = action {
+            self.consecutive_timeouts += 1;
+            self.max_consecutive_timeouts =
+                self.max_consecutive_timeouts.max(self.consecutive_timeouts);
+        } else {
+            self.consecutive_timeouts = 0;
+        }
+
+        match action {
+            proto::PlayerAction::Timeout => self.timeouts += 1,
+            proto::PlayerAction::ParseError(_) => self.parse_errors += 1,
+            proto::PlayerAction::Commands(commands) => {
+                for error in commands.iter().filter_map(|c| c.error.as_ref()) {
+                    match error {
+                        CommandError::NotEnoughShips => self.not_enough_ships += 1,
+                        CommandError::OriginNotOwned => self.origin_not_owned += 1,
+                        CommandError::ZeroShipMove => self.zero_ship_move += 1,
+                        CommandError::OriginDoesNotExist => self.origin_does_not_exist += 1,
+                        CommandError::DestinationDoesNotExist => {
+                            self.destination_does_not_exist += 1
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number of commands that were refused.
+    pub fn invalid_commands(&self) -> u64 {
+        self.not_enough_ships
+            + self.origin_not_owned
+            + self.zero_ship_move
+            + self.origin_does_not_exist
+            + self.destination_does_not_exist
+    }
+
+    /// Timeouts, parse errors and refused commands together.
+    pub fn errors(&self) -> u64 {
+        self.timeouts + self.parse_errors + self.invalid_commands()
+    }
+}
+
+/// When players are disqualified, a limit that is not set never disqualifies.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisqualifyPolicy {
+    /// Timeouts in a row.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consecutive_timeouts: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_errors: Option<u64>,
+    /// Timeouts, parse errors and refused commands together.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_budget: Option<u64>,
+}
+
+impl DisqualifyPolicy {
+    /// Limits of 0 are refused, they would disqualify every player on the first turn.
+    pub fn validate(&self) -> Result<(), String> {
+        let limits = [
+            ("consecutive_timeouts", self.consecutive_timeouts),
+            ("parse_errors", self.parse_errors),
+            ("error_budget", self.error_budget),
+        ];
+        match limits.iter().find(|(_, limit)| *limit == Some(0)) {
+            Some((name, _)) => Err(format!("the {} limit has to be at least 1", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// The limit the player crossed, if any.
+    pub fn check(&self, stats: &PlayerStats) -> Option<DisqualifyReason> {
+        let crossed = |limit: Option<u64>, value: u64| limit.map_or(false, |l| value >= l);
+
+        if crossed(self.consecutive_timeouts, stats.consecutive_timeouts) {
+            Some(DisqualifyReason::ConsecutiveTimeouts(stats.consecutive_timeouts))
+        } else if crossed(self.parse_errors, stats.parse_errors) {
+            Some(DisqualifyReason::ParseErrors(stats.parse_errors))
+        } else if crossed(self.error_budget, stats.errors()) {
+            Some(DisqualifyReason::ErrorBudget(stats.errors()))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum DisqualifyReason {
+    ConsecutiveTimeouts(u64),
+    ParseErrors(u64),
+    ErrorBudget(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Disqualification {
+    pub turn: u64,
+    pub reason: DisqualifyReason,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(actions: &[proto::PlayerAction]) -> PlayerStats {
+        let mut stats = PlayerStats::default();
+        for action in actions {
+            stats.record(action);
+        }
+        stats
+    }
+
+    fn parse_error() -> proto::PlayerAction {
+        proto::PlayerAction::ParseError("garbage".to_string())
+    }
+
+    #[test]
+    fn a_turn_in_time_resets_the_consecutive_timeouts() {
+        use proto::PlayerAction::{Commands, Timeout};
+        let stats = stats(&[Timeout, Timeout, Timeout, Commands(Vec::new()), Timeout]);
+
+        assert_eq!(stats.timeouts, 4);
+        assert_eq!(stats.consecutive_timeouts, 1);
+        assert_eq!(stats.max_consecutive_timeouts, 3);
+
+        // A parse error is an answer in time as well
+        let stats = self::stats(&[Timeout, parse_error(), Timeout]);
+        assert_eq!(stats.consecutive_timeouts, 1);
+        assert_eq!(stats.max_consecutive_timeouts, 1);
+    }
+
+    #[test]
+    fn every_limit_disqualifies() {
+        use proto::PlayerAction::Timeout;
+        let timeouts = stats(&[Timeout, Timeout, Timeout]);
+        let parse_errors = stats(&[parse_error(), parse_error()]);
+        let policy = |consecutive_timeouts, parse_errors, error_budget| DisqualifyPolicy {
+            consecutive_timeouts,
+            parse_errors,
+            error_budget,
+        };
+
+        assert_eq!(
+            policy(Some(3), None, None).check(&timeouts),
+            Some(DisqualifyReason::ConsecutiveTimeouts(3))
+        );
+        assert_eq!(policy(Some(4), None, None).check(&timeouts), None);
+        assert_eq!(
+            policy(None, Some(2), None).check(&parse_errors),
+            Some(DisqualifyReason::ParseErrors(2))
+        );
+        assert_eq!(policy(None, Some(3), None).check(&parse_errors), None);
+        assert_eq!(
+            policy(None, None, Some(3)).check(&timeouts),
+            Some(DisqualifyReason::ErrorBudget(3))
+        );
+        assert_eq!(policy(None, None, Some(3)).check(&parse_errors), None);
+        assert_eq!(DisqualifyPolicy::default().check(&timeouts), None);
+    }
+
+    #[test]
+    fn consecutive_timeouts_come_before_parse_errors_before_the_error_budget() {
+        use proto::PlayerAction::Timeout;
+        let stats = stats(&[parse_error(), parse_error(), Timeout, Timeout]);
+        let all = DisqualifyPolicy {
+            consecutive_timeouts: Some(2),
+            parse_errors: Some(2),
+            error_budget: Some(4),
+        };
+
+        assert_eq!(
+            all.check(&stats),
+            Some(DisqualifyReason::ConsecutiveTimeouts(2))
+        );
+        let policy = DisqualifyPolicy {
+            consecutive_timeouts: None,
+            ..all.clone()
+        };
+        assert_eq!(policy.check(&stats), Some(DisqualifyReason::ParseErrors(2)));
+        let policy = DisqualifyPolicy {
+            parse_errors: None,
+            ..policy
+        };
+        assert_eq!(policy.check(&stats), Some(DisqualifyReason::ErrorBudget(4)));
+    }
+
+    #[test]
+    fn limits_of_zero_are_refused() {
+        assert_eq!(DisqualifyPolicy::default().validate(), Ok(()));
+        let policies = [
+            DisqualifyPolicy {
+                consecutive_timeouts: Some(0),
+                ..Default::default()
+            },
+            DisqualifyPolicy {
+                parse_errors: Some(0),
+                ..Default::default()
+            },
+            DisqualifyPolicy {
+                error_budget: Some(0),
+                ..Default::default()
+            },
+        ];
+        for policy in policies.iter() {
+            assert!(policy.validate().is_err(), "{:?}", policy);
+            let policy = DisqualifyPolicy {
+                consecutive_timeouts: policy.consecutive_timeouts.map(|_| 1),
+                parse_errors: policy.parse_errors.map(|_| 1),
+                error_budget: policy.error_budget.map(|_| 1),
+            };
+            assert_eq!(policy.validate(), Ok(()));
+        }
+    }
+}
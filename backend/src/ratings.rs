@@ -0,0 +1,399 @@
+use crate::planetwars::FinishedState;
+use crate::store::{GameStatus, GameStore};
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::f64::consts::PI;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const START_ELO: f64 = 1500.0;
+/// How much a single 2 player game can change an elo rating.
+const ELO_K: f64 = 32.0;
+
+const START_MU: f64 = 25.0;
+const START_SIGMA: f64 = START_MU / 3.0;
+/// Performance variance of a bot in a single game.
+const BETA: f64 = START_SIGMA / 2.0;
+/// Uncertainty added before every game, so ratings keep moving.
+const TAU: f64 = START_SIGMA / 100.0;
+
+/// The rating of one bot, identified by its name.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rating {
+    /// Elo rating, updated by 2 player games.
+    pub elo: f64,
+    /// TrueSkill-style skill estimate and its uncertainty, updated by multiplayer games.
+    pub mu: f64,
+    pub sigma: f64,
+    pub games: u64,
+    /// Games the bot won alone.
+    pub wins: u64,
+    /// Games where the bot shared the first place.
+    #[serde(default)]
+    pub draws: u64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            elo: START_ELO,
+            mu: START_MU,
+            sigma: START_SIGMA,
+            games: 0,
+            wins: 0,
+            draws: 0,
+        }
+    }
+}
+
+/// A row of the leaderboard.
+#[derive(Serialize, Debug)]
+pub struct Standing {
+    pub name: String,
+    #[serde(flatten)]
+    pub rating: Rating,
+    /// Conservative skill estimate, the skill the bot has with high certainty.
+    pub skill: f64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Ladder {
+    ratings: BTreeMap<String, Rating>,
+    /// Ids of the games that are already accounted for.
+    rated: BTreeSet<String>,
+}
+
+/// Persistent ratings of all bots, updated whenever a game finishes.
+pub struct Ratings {
+    path: PathBuf,
+    inner: Mutex<Ladder>,
+}
+
+impl Ratings {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let ladder = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(file)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ladder::default(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Ratings {
+            path,
+            inner: Mutex::new(ladder),
+        })
+    }
+
+    /// Updates the ratings with the result of a finished game, games are only rated once.
+    /// Games with fewer than 2 bots or the same bot more than once are not rated.
+    pub fn rate(&self, game_id: &str, state: &FinishedState) -> io::Result<()> {
+        let mut ladder = self.inner.lock().unwrap();
+        if !ladder.rated.insert(game_id.to_string()) {
+            return Ok(());
+        }
+
+        let names: BTreeSet<&String> = state.players.iter().map(|(_, name)| name).collect();
+        if state.players.len() >= 2 && names.len() == state.players.len() {
+            let results: Vec<(String, usize)> = state
+                .players
+                .iter()
+                .map(|(id, name)| (name.clone(), rank(state, *id)))
+                .collect();
+
+            ladder.update(&results);
+        }
+
+        self.save(&ladder)
+    }
+
+    /// Rates the finished games in the store that are not rated yet, oldest first.
+    pub fn rate_finished(&self, store: &GameStore) -> io::Result<()> {
+        let mut finished: Vec<(String, FinishedState)> = store
+            .get_games()
+            .into_iter()
+            .filter_map(|game| match game.status {
+                GameStatus::Finished { state } => Some((game.id, state)),
+                _ => None,
+            })
+            .collect();
+        finished.sort_by_key(|(_, state)| state.time);
+
+        for (id, state) in finished {
+            self.rate(&id, &state)?;
+        }
+        Ok(())
+    }
+
+    /// All rated bots, best elo first.
+    pub fn leaderboard(&self) -> Vec<Standing> {
+        let ladder = self.inner.lock().unwrap();
+        let mut standings: Vec<Standing> = ladder
+            .ratings
+            .iter()
+            .map(|(name, rating)| Standing {
+                name: name.clone(),
+                skill: rating.mu - 3.0 * rating.sigma,
+                rating: rating.clone(),
+            })
+            .collect();
+
+        standings.sort_by(|a, b| {
+            b.rating
+                .elo
+                .total_cmp(&a.rating.elo)
+                .then(b.skill.total_cmp(&a.skill))
+        });
+        standings
+    }
+
+    fn save(&self, ladder: &Ladder) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(ladder).unwrap())?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl Ladder {
+    /// Updates the ratings of the bots in a game, with their ranks.
+    fn update(&mut self, results: &[(String, usize)]) {
+        let before: Vec<Rating> = results
+            .iter()
+            .map(|(name, _)| self.ratings.get(name).cloned().unwrap_or_default())
+            .collect();
+        let mut after = before.clone();
+
+        if results.len() == 2 {
+            let score = match results[0].1.cmp(&results[1].1) {
+                Ordering::Less => 1.0,
+                Ordering::Greater => 0.0,
+                Ordering::Equal => 0.5,
+            };
+            let delta = elo_delta(before[0].elo, before[1].elo, score);
+            after[0].elo += delta;
+            after[1].elo -= delta;
+        } else {
+            update_skills(&before, &mut after, results);
+        }
+
+        let first = results.iter().filter(|&&(_, rank)| rank == 1).count();
+        for ((name, rank), mut rating) in results.iter().zip(after.into_iter()) {
+            rating.games += 1;
+            match (*rank, first) {
+                (1, 1) => rating.wins += 1,
+                (1, _) => rating.draws += 1,
+                _ => (),
+            }
+            self.ratings.insert(name.clone(), rating);
+        }
+    }
+}
+
+/// Change of the elo rating of player a, scoring `score` against player b.
+fn elo_delta(a: f64, b: f64, score: f64) -> f64 {
+    let expected = 1.0 / (1.0 + 10f64.powf((b - a) / 400.0));
+    ELO_K * (score - expected)
+}
+
+/// TrueSkill-style update of a multiplayer game.
+/// Every bot is compared to every bot it outranked with the two player TrueSkill update,
+/// the changes of a bot are averaged over its comparisons.
+/// Bots that share a rank are not compared, so a game where all bots tie says nothing about skill.
+fn update_skills(before: &[Rating], after: &mut [Rating], results: &[(String, usize)]) {
+    let n = results.len();
+    let mut mu_delta = vec![0.0; n];
+    let mut sigma_factor = vec![0.0; n];
+    let mut comparisons = vec![0usize; n];
+
+    for w in 0..n {
+        for l in (0..n).filter(|&l| results[w].1 < results[l].1) {
+            let var_w = before[w].sigma.powi(2) + TAU.powi(2);
+            let var_l = before[l].sigma.powi(2) + TAU.powi(2);
+
+            let c = (2.0 * BETA.powi(2) + var_w + var_l).sqrt();
+            let t = (before[w].mu - before[l].mu) / c;
+            let v = pdf(t) / cdf(t);
+            let w_factor = v * (v + t);
+
+            mu_delta[w] += var_w / c * v;
+            mu_delta[l] -= var_l / c * v;
+            sigma_factor[w] += var_w / c.powi(2) * w_factor;
+            sigma_factor[l] += var_l / c.powi(2) * w_factor;
+            comparisons[w] += 1;
+            comparisons[l] += 1;
+        }
+    }
+
+    for i in (0..n).filter(|&i| comparisons[i] > 0) {
+        let count = comparisons[i] as f64;
+        let var = before[i].sigma.powi(2) + TAU.powi(2);
+
+        after[i].mu = before[i].mu + mu_delta[i] / count;
+        after[i].sigma = (var * (1.0 - sigma_factor[i] / count).max(1e-4)).sqrt();
+    }
+}
+
+/// The rank of a player in a finished game.
+/// Games from before rankings only know their winners, the others share the second place.
+fn rank(state: &FinishedState, player: u64) -> usize {
+    match state.ranking.iter().find(|r| r.player as u64 == player) {
+        Some(ranked) => ranked.rank,
+        None if state.winners.contains(&player) => 1,
+        None => 2,
+    }
+}
+
+fn pdf(x: f64) -> f64 {
+    (-x * x / 2.0).exp() / (2.0 * PI).sqrt()
+}
+
+fn cdf(x: f64) -> f64 {
+    erfc(-x / 2f64.sqrt()) / 2.0
+}
+
+/// Complementary error function, with a fractional error below 1.2e-7.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t * (-z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+        .exp();
+
+    if x >= 0.0 {
+        r
+    } else {
+        2.0 - r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn results(ranks: &[usize]) -> Vec<(String, usize)> {
+        ranks
+            .iter()
+            .enumerate()
+            .map(|(i, rank)| (format!("bot{}", i), *rank))
+            .collect()
+    }
+
+    fn rating(ladder: &Ladder, i: usize) -> Rating {
+        ladder.ratings[&format!("bot{}", i)].clone()
+    }
+
+    fn finished(winner: u64) -> FinishedState {
+        FinishedState {
+            winners: vec![winner],
+            ranking: Vec::new(),
+            turns: 100,
+            name: "game".to_string(),
+            file: "game.json".to_string(),
+            map: "hex".to_string(),
+            time: SystemTime::now(),
+            players: vec![(0, "bot0".to_string()), (1, "bot1".to_string())],
+            combat: Default::default(),
+            victory: Default::default(),
+            stats: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn elo_moves_more_for_an_upset() {
+        assert_eq!(elo_delta(1500.0, 1500.0, 1.0), ELO_K / 2.0);
+        assert_eq!(elo_delta(1500.0, 1500.0, 0.5), 0.0);
+        assert!(elo_delta(1400.0, 1600.0, 1.0) > elo_delta(1600.0, 1400.0, 1.0));
+    }
+
+    #[test]
+    fn two_player_games_update_the_elo() {
+        let mut ladder = Ladder::default();
+        ladder.update(&results(&[1, 2]));
+
+        let (winner, loser) = (rating(&ladder, 0), rating(&ladder, 1));
+        assert_eq!(winner.elo, START_ELO + ELO_K / 2.0);
+        assert_eq!(loser.elo, START_ELO - ELO_K / 2.0);
+        assert_eq!((winner.games, winner.wins), (1, 1));
+        assert_eq!((loser.games, loser.wins), (1, 0));
+        assert_eq!(winner.mu, START_MU);
+    }
+
+    #[test]
+    fn multiplayer_games_update_the_skill() {
+        let mut ladder = Ladder::default();
+        ladder.update(&results(&[1, 2, 3]));
+
+        let ratings: Vec<Rating> = (0..3).map(|i| rating(&ladder, i)).collect();
+        assert!(ratings[0].mu > START_MU);
+        assert!(ratings[2].mu < START_MU);
+        assert!(ratings[0].mu > ratings[1].mu && ratings[1].mu > ratings[2].mu);
+        assert!(ratings.iter().all(|r| r.sigma < START_SIGMA));
+        assert!(ratings.iter().all(|r| r.elo == START_ELO));
+    }
+
+    #[test]
+    fn a_multiplayer_draw_says_nothing_about_skill() {
+        let mut ladder = Ladder::default();
+        ladder.update(&results(&[1, 1, 1]));
+
+        for i in 0..3 {
+            let rating = rating(&ladder, i);
+            assert_eq!((rating.mu, rating.sigma), (START_MU, START_SIGMA));
+            assert_eq!((rating.games, rating.wins, rating.draws), (1, 0, 1));
+        }
+    }
+
+    #[test]
+    fn only_a_bot_alone_at_the_first_place_wins() {
+        let mut ladder = Ladder::default();
+        ladder.update(&results(&[1, 1]));
+        ladder.update(&results(&[2, 1]));
+        ladder.update(&results(&[1, 1, 3]));
+
+        let counts = |i| {
+            let rating = rating(&ladder, i);
+            (rating.games, rating.wins, rating.draws)
+        };
+        assert_eq!(counts(0), (3, 0, 2));
+        assert_eq!(counts(1), (3, 1, 2));
+        assert_eq!(counts(2), (1, 0, 0));
+    }
+
+    #[test]
+    fn games_are_rated_once() {
+        let path = std::env::temp_dir().join(format!("ratings-{}.json", std::process::id()));
+        let ratings = Ratings::open(&path).unwrap();
+        ratings.rate("game", &finished(0)).unwrap();
+        ratings.rate("game", &finished(1)).unwrap();
+
+        let reopened = Ratings::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let standings = reopened.leaderboard();
+        let rating = &standings[0].rating;
+        assert_eq!(standings[0].name, "bot0");
+        assert_eq!((rating.games, rating.wins), (1, 1));
+    }
+
+    #[test]
+    fn the_leaderboard_survives_nan() {
+        let mut ladder = Ladder::default();
+        ladder.update(&results(&[1, 2]));
+        ladder.ratings.get_mut("bot1").unwrap().elo = f64::NAN;
+        let ratings = Ratings {
+            path: PathBuf::new(),
+            inner: Mutex::new(ladder),
+        };
+
+        assert_eq!(ratings.leaderboard().len(), 2);
+    }
+}
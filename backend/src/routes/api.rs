@@ -0,0 +1,168 @@
+use crate::planetwars;
+use crate::planetwars::pw_feed::Feeds;
+use crate::ratings::{Ratings, Standing};
+use crate::store::{GameRecord, GameStore};
+use crate::util::*;
+
+use super::lobby::{get_maps, get_states, Map};
+
+use rocket::http::{ContentType, Status};
+use rocket::response::content::Content;
+use rocket::response::Stream;
+use rocket::tokio::{
+    self,
+    io::{AsyncWriteExt, DuplexStream},
+};
+use rocket::{Route, State};
+use rocket_contrib::json::Json;
+
+use mozaic::modules::game;
+
+use async_std::fs;
+use futures::StreamExt;
+
+use std::sync::Arc;
+
+/// How many bytes of events can wait for a slow spectator.
+const SPECTATE_BUFFER: usize = 1 << 20;
+
+/// A game as returned by the api, identified by its id.
+#[derive(Serialize)]
+struct ApiGame {
+    id: String,
+    #[serde(flatten)]
+    game: GameState,
+}
+
+impl From<GameState> for ApiGame {
+    fn from(game: GameState) -> Self {
+        ApiGame {
+            id: game.id().to_string(),
+            game,
+        }
+    }
+}
+
+/// Games on a page of the games api by default.
+const GAMES_PER_PAGE: usize = 50;
+
+/// Games on a page of the games api at most.
+const MAX_GAMES_PER_PAGE: usize = 500;
+
+/// A page of all games, both running and finished ones, most recent first.
+/// Pages start at 1.
+#[get("/api/v1/games?<page>&<per_page>")]
+async fn games_get(
+    page: Option<usize>,
+    per_page: Option<usize>,
+    gm: State<'_, Arc<game::Manager>>,
+    store: State<'_, Arc<GameStore>>,
+    ratings: State<'_, Arc<Ratings>>,
+) -> Result<Json<Vec<ApiGame>>, JsonError> {
+    let per_page = per_page.unwrap_or(GAMES_PER_PAGE).min(MAX_GAMES_PER_PAGE);
+    let games = store.get_page(page.unwrap_or(1), per_page);
+    let mut games = get_game_states(&gm, &store, &ratings, games).await?;
+    games.sort_by(|a, b| b.time().cmp(&a.time()));
+    Ok(Json(games.into_iter().map(ApiGame::from).collect()))
+}
+
+/// One game, by its id.
+#[get("/api/v1/games/<id>")]
+async fn game_get(
+    id: String,
+    gm: State<'_, Arc<game::Manager>>,
+    store: State<'_, Arc<GameStore>>,
+    ratings: State<'_, Arc<Ratings>>,
+) -> Result<Json<ApiGame>, JsonError> {
+    let game = store
+        .get_game(&id)
+        .ok_or_else(|| json_error(Status::NotFound, format!("Game {} does not exist", id)))?;
+    let mut games = get_game_states(&gm, &store, &ratings, vec![game]).await?;
+    Ok(Json(games.remove(0).into()))
+}
+
+/// Streams a running game as server-sent events, starting with every turn so far.
+/// Every event is a line of the game log, the stream ends with a `finished` event.
+#[get("/api/v1/games/<id>/spectate")]
+async fn spectate_get(
+    id: String,
+    feeds: State<'_, Arc<Feeds>>,
+) -> Result<Content<Stream<DuplexStream>>, JsonError> {
+    let mut turns = feeds
+        .subscribe(&id)
+        .ok_or_else(|| json_error(Status::NotFound, format!("Game {} is not running", id)))?;
+
+    let (mut events, body) = tokio::io::duplex(SPECTATE_BUFFER);
+    tokio::spawn(async move {
+        while let Some(turn) = turns.next().await {
+            let event = format!("data: {}\n\n", turn);
+            if events.write_all(event.as_bytes()).await.is_err() {
+                // The spectator left
+                return;
+            }
+        }
+        let _ = events.write_all(b"event: finished\ndata:\n\n").await;
+    });
+
+    Ok(Content(
+        ContentType::new("text", "event-stream"),
+        Stream::from(body),
+    ))
+}
+
+/// All available maps.
+#[get("/api/v1/maps")]
+async fn maps_get() -> Result<Json<Vec<Map>>, JsonError> {
+    get_maps()
+        .await
+        .map(Json)
+        .map_err(|e| json_error(Status::InternalServerError, e))
+}
+
+/// One map, by its name.
+#[get("/api/v1/maps/<name>")]
+async fn map_get(name: String) -> Result<Json<planetwars::Map>, JsonError> {
+    let not_found = || json_error(Status::NotFound, format!("Map {} does not exist", name));
+
+    if name.contains("..") {
+        return Err(not_found());
+    }
+
+    let content = fs::read_to_string(format!("maps/{}.json", name))
+        .await
+        .map_err(|_| not_found())?;
+
+    serde_json::from_str(&content)
+        .map(Json)
+        .map_err(|e| json_error(Status::InternalServerError, e.to_string()))
+}
+
+/// The ratings of all bots, best first.
+#[get("/api/v1/leaderboard")]
+async fn leaderboard_get(ratings: State<'_, Arc<Ratings>>) -> Json<Vec<Standing>> {
+    Json(ratings.leaderboard())
+}
+
+/// The states of the given stored games, running, finished or interrupted.
+async fn get_game_states(
+    gm: &game::Manager,
+    store: &GameStore,
+    ratings: &Ratings,
+    games: Vec<GameRecord>,
+) -> Result<Vec<GameState>, JsonError> {
+    get_states(store, ratings, gm, games)
+        .await
+        .map_err(|e| json_error(Status::InternalServerError, e))
+}
+
+/// Fuels the api routes
+pub fn fuel(routes: &mut Vec<Route>) {
+    routes.extend(routes![
+        games_get,
+        game_get,
+        spectate_get,
+        maps_get,
+        map_get,
+        leaderboard_get
+    ]);
+}
@@ -0,0 +1,64 @@
+use crate::hosted::{HostedBot, HostedBots, Language, UploadError};
+use crate::util::*;
+
+use rocket::http::Status;
+use rocket::{Route, State};
+use rocket_contrib::json::Json;
+
+use std::sync::Arc;
+
+/// The json body to upload a bot.
+#[derive(Deserialize, Debug)]
+struct BotReq {
+    name: String,
+    language: Language,
+    source: String,
+    /// The token of the owner, to replace a bot that was uploaded before.
+    #[serde(default)]
+    token: Option<String>,
+}
+
+/// An uploaded bot with the token of its owner.
+#[derive(Serialize, Debug)]
+struct BotRes {
+    #[serde(flatten)]
+    bot: HostedBot,
+    token: String,
+}
+
+/// Uploads the source of a bot, the server runs it in games that ask for it.
+/// Uploading a bot with the same name again replaces it, with the token of the first upload.
+#[post("/bots", data = "<req>")]
+async fn bot_post(
+    req: Json<BotReq>,
+    hosted: State<'_, Arc<HostedBots>>,
+) -> Result<Json<BotRes>, JsonError> {
+    let BotReq {
+        name,
+        language,
+        source,
+        token,
+    } = req.into_inner();
+
+    HostedBots::check(&name, &source).map_err(|e| json_error(Status::BadRequest, e))?;
+
+    hosted
+        .upload(&name, language, &source, token.as_deref())
+        .map(|(bot, token)| Json(BotRes { bot, token }))
+        .map_err(|e| match e {
+            UploadError::NotOwner(_) => json_error(Status::Forbidden, e),
+            UploadError::Io(_) => json_error(Status::InternalServerError, e),
+            _ => json_error(Status::BadRequest, e),
+        })
+}
+
+/// All uploaded bots.
+#[get("/api/v1/bots")]
+async fn bots_get(hosted: State<'_, Arc<HostedBots>>) -> Json<Vec<HostedBot>> {
+    Json(hosted.get_bots())
+}
+
+/// Fuels the bot routes
+pub fn fuel(routes: &mut Vec<Route>) {
+    routes.extend(routes![bot_post, bots_get]);
+}
@@ -1,6 +1,14 @@
+use crate::hosted::HostedBots;
+use crate::planetwars::pw_ai::Ai;
+use crate::planetwars::pw_feed::{Feed, Feeds};
+use crate::planetwars::pw_rules::{CombatRule, Victory};
+use crate::planetwars::pw_stats::DisqualifyPolicy;
 use crate::planetwars::{self, FinishedState};
+use crate::ratings::Ratings;
+use crate::store::{self, GameRecord, GameStatus, GameStore};
 use crate::util::*;
 
+use rocket::http::Status;
 use rocket::{Route, State};
 use rocket_contrib::json::Json;
 use rocket_contrib::templates::Template;
@@ -13,21 +21,51 @@ use async_std::fs;
 use async_std::prelude::StreamExt;
 
 use futures::executor::ThreadPool;
-use futures::future::{join_all, FutureExt};
+use futures::future::join_all;
 
 use serde_json::Value;
 
 use rand::prelude::*;
-use std::time::SystemTime;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Games that are not running anymore shown in the lobby, the most recent ones.
+const LOBBY_GAMES: usize = 50;
+/// How often the running games are checked for having finished.
+const FINISH_INTERVAL: Duration = Duration::from_secs(1);
 
 /// The type required to build a game.
 /// (json in POST request).
 #[derive(Deserialize, Debug)]
 struct GameReq {
+    /// Number of players, can be left out when `players` is given.
+    #[serde(default)]
     nop: u64,
+    /// Who plays every player: `"key"` for a bot that connects with a key,
+    /// or a built-in AI like `"ai:greedy"`. All players connect when left out.
+    #[serde(default)]
+    players: Vec<String>,
     max_turns: u64,
     map: String,
     name: String,
+    #[serde(default)]
+    combat: CombatRule,
+    #[serde(default)]
+    victory: Victory,
+    #[serde(default)]
+    vision: Option<f64>,
+    /// In milliseconds
+    #[serde(default = "planetwars::pw_config::default_turn_timeout")]
+    turn_timeout: u64,
+    /// In milliseconds
+    #[serde(default)]
+    time_bank: Option<u64>,
+    #[serde(default)]
+    disqualify: Option<DisqualifyPolicy>,
+    /// Uploaded bots that play as the first players with a key, the server runs them itself.
+    #[serde(default)]
+    hosted: Vec<String>,
 }
 
 /// Response when building a game.
@@ -40,11 +78,12 @@ struct GameRes {
 /// Standard get function for the lobby tab
 #[get("/lobby")]
 async fn get_lobby(
-    gm: State<'_, game::Manager>,
-    state: State<'_, Games>,
+    gm: State<'_, Arc<game::Manager>>,
+    store: State<'_, Arc<GameStore>>,
+    ratings: State<'_, Arc<Ratings>>,
 ) -> Result<Template, String> {
     let maps = get_maps().await?;
-    let games = get_states(&state.get_games(), &gm).await?;
+    let games = get_states(&store, &ratings, &gm, store.get_recent_games(LOBBY_GAMES)).await?;
     let context = Context::new_with("Lobby", Lobby { games, maps });
     Ok(Template::render("lobby", &context))
 }
@@ -52,10 +91,11 @@ async fn get_lobby(
 /// The lobby get's this automatically on load and on refresh.
 #[get("/partial/state")]
 async fn state_get(
-    gm: State<'_, game::Manager>,
-    state: State<'_, Games>,
+    gm: State<'_, Arc<game::Manager>>,
+    store: State<'_, Arc<GameStore>>,
+    ratings: State<'_, Arc<Ratings>>,
 ) -> Result<Template, String> {
-    let games = get_states(&state.get_games(), &gm).await?;
+    let games = get_states(&store, &ratings, &gm, store.get_recent_games(LOBBY_GAMES)).await?;
     let context = Context::new_with(
         "Lobby",
         Lobby {
@@ -73,33 +113,176 @@ async fn state_get(
 async fn post_game(
     game_req: Json<GameReq>,
     tp: State<'_, ThreadPool>,
-    gm: State<'_, game::Manager>,
-    state: State<'_, Games>,
-) -> Result<Json<GameRes>, String> {
-    let game = build_builder(
+    gm: State<'_, Arc<game::Manager>>,
+    store: State<'_, Arc<GameStore>>,
+    feeds: State<'_, Arc<Feeds>>,
+    hosted: State<'_, Arc<HostedBots>>,
+) -> Result<Json<GameRes>, JsonError> {
+    let (nop, ai) = player_slots(&game_req)?;
+    let keyed = nop as usize - ai.len();
+    if keyed == 0 {
+        return Err(json_error(
+            Status::BadRequest,
+            "A game needs at least one player that connects",
+        ));
+    }
+    if game_req.hosted.len() > keyed {
+        return Err(json_error(
+            Status::BadRequest,
+            "There are more hosted bots than players",
+        ));
+    }
+    if let Some(name) = game_req
+        .hosted
+        .iter()
+        .find(|name| hosted.get_bot(name).is_none())
+    {
+        return Err(json_error(
+            Status::BadRequest,
+            format!("Bot {} is not uploaded", name),
+        ));
+    }
+    if let Some(policy) = &game_req.disqualify {
+        policy
+            .validate()
+            .map_err(|e| json_error(Status::BadRequest, e))?;
+    }
+
+    let config = planetwars::Config {
+        map_file: game_req.map.clone(),
+        max_turns: game_req.max_turns,
+        combat: game_req.combat,
+        victory: game_req.victory,
+        vision: game_req.vision,
+        turn_timeout: game_req.turn_timeout,
+        time_bank: game_req.time_bank,
+        disqualify: game_req.disqualify.clone(),
+    };
+    config
+        .validate()
+        .map_err(|e| json_error(Status::BadRequest, e))?;
+
+    let game = start_game(
         tp.inner().clone(),
-        game_req.nop,
-        game_req.max_turns,
-        &game_req.map,
+        &gm,
+        &store,
+        &feeds,
+        config,
+        nop,
+        ai,
         &game_req.name,
-    );
+    )
+    .await?;
+
+    let keys = game.players.iter().filter(|&&key| key != 0);
+    for (name, key) in game_req.hosted.iter().zip(keys) {
+        hosted
+            .launch(name, *key)
+            .map_err(|e| json_error(Status::InternalServerError, e.to_string()))?;
+    }
+
+    Ok(Json(GameRes {
+        players: game.players,
+        state: game.state,
+    }))
+}
+
+/// The number of players of a game request, and the players that are played by an AI.
+fn player_slots(game_req: &GameReq) -> Result<(u64, Vec<(usize, Ai)>), JsonError> {
+    if game_req.players.is_empty() {
+        return Ok((game_req.nop, Vec::new()));
+    }
+    if game_req.nop != 0 && game_req.nop != game_req.players.len() as u64 {
+        return Err(json_error(
+            Status::BadRequest,
+            "The number of players does not match the players",
+        ));
+    }
+
+    let mut ai = Vec::new();
+    for (id, player) in game_req.players.iter().enumerate() {
+        if player == "key" {
+            continue;
+        }
+        match Ai::from_player(player) {
+            Some(player_ai) => ai.push((id, player_ai)),
+            None => {
+                return Err(json_error(
+                    Status::BadRequest,
+                    format!("Unknown player {}, use \"key\" or one of {}", player, ai_names()),
+                ))
+            }
+        }
+    }
+
+    Ok((game_req.players.len() as u64, ai))
+}
+
+fn ai_names() -> String {
+    Ai::ALL
+        .iter()
+        .map(|ai| format!("{}{}", Ai::PREFIX, ai.name()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A game that was just started.
+pub struct StartedGame {
+    pub id: String,
+    /// Connection keys of the players, in player order, 0 for AI players.
+    pub players: Vec<u64>,
+    pub state: Value,
+}
+
+/// Starts a game and saves it in the store, spectators can follow it through its feed.
+pub async fn start_game(
+    pool: ThreadPool,
+    gm: &game::Manager,
+    store: &GameStore,
+    feeds: &Feeds,
+    config: planetwars::Config,
+    nop: u64,
+    ai: Vec<(usize, Ai)>,
+    name: &str,
+) -> Result<StartedGame, JsonError> {
+    let file = generate_string_id();
+    let id = store::file_id(&file).to_string();
+    let game = build_builder(pool, nop, ai, config.clone(), &file, name, feeds.create(&id))
+        .map_err(|e| json_error(Status::BadRequest, e))?;
     let game_id = gm.start_game(game).await.unwrap();
-    state.add_game(game_req.name.clone(), game_id);
+
+    store
+        .save(GameRecord {
+            id: id.clone(),
+            name: name.to_string(),
+            map: Path::new(&config.map_file)
+                .file_stem()
+                .and_then(|x| x.to_str())
+                .unwrap_or_default()
+                .to_string(),
+            players: nop as usize,
+            config: Some(config),
+            file,
+            time: SystemTime::now(),
+            status: GameStatus::Running {
+                manager_id: game_id,
+            },
+        })
+        .map_err(|e| json_error(Status::InternalServerError, e.to_string()))?;
 
     match gm.get_state(game_id).await {
         Some(Ok((state, conns))) => {
-            let players: Vec<u64> = conns
-                .iter()
-                .map(|conn| match conn {
-                    Connect::Waiting(_, key) => *key,
-                    _ => 0,
-                })
-                .collect();
-
-            Ok(Json(GameRes { players, state }))
+            let mut players = vec![0; nop as usize];
+            for conn in conns.iter() {
+                if let Connect::Waiting(id, key) = conn {
+                    players[*id as usize] = *key;
+                }
+            }
+
+            Ok(StartedGame { id, players, state })
         }
-        Some(Err(v)) => Err(serde_json::to_string(&v).unwrap()),
-        None => Err(String::from("Fuck the world")),
+        Some(Err(v)) => Err(json_error(Status::InternalServerError, v)),
+        None => Err(json_error(Status::InternalServerError, "Fuck the world")),
     }
 }
 
@@ -117,29 +300,27 @@ fn generate_string_id() -> String {
 /// This returns such a Builder for a planetwars game.
 fn build_builder(
     pool: ThreadPool,
-    number_of_clients: u64,
-    max_turns: u64,
-    map: &str,
+    number_of_players: u64,
+    ai: Vec<(usize, Ai)>,
+    config: planetwars::Config,
+    location: &str,
     name: &str,
-) -> game::Builder<planetwars::PlanetWarsGame> {
-    let config = planetwars::Config {
-        map_file: map.to_string(),
-        max_turns: max_turns,
-    };
-
-    let game = planetwars::PlanetWarsGame::new(
-        config.create_game(number_of_clients as usize),
-        &generate_string_id(),
-        name,
-        map,
-    );
+    feed: Feed,
+) -> Result<game::Builder<planetwars::PlanetWarsGame>, planetwars::MapError> {
+    // AI players are played by the game itself, only the others get a connection
+    let players: Vec<PlayerId> = (0..number_of_players)
+        .filter(|&id| ai.iter().all(|&(ai_id, _)| ai_id as u64 != id))
+        .collect();
 
-    let players: Vec<PlayerId> = (0..number_of_clients).collect();
+    let game =
+        planetwars::PlanetWarsGame::new(config, number_of_players as usize, location, name)?
+            .with_feed(feed)
+            .with_ai(ai);
 
-    game::Builder::new(players.clone(), game).with_step_lock(
+    Ok(game::Builder::new(players.clone(), game).with_step_lock(
         StepLock::new(players.clone(), pool.clone())
-            .with_timeout(std::time::Duration::from_secs(1)),
-    )
+            .with_timeout(planetwars::TICK),
+    ))
 }
 
 /// Fuels the lobby routes
@@ -159,7 +340,7 @@ pub struct Map {
     url: String,
 }
 
-async fn get_maps() -> Result<Vec<Map>, String> {
+pub async fn get_maps() -> Result<Vec<Map>, String> {
     let mut maps = Vec::new();
     let mut entries = fs::read_dir("maps")
         .await
@@ -177,42 +358,65 @@ async fn get_maps() -> Result<Vec<Map>, String> {
     Ok(maps)
 }
 
+/// Saves and rates the games that finished every second, as long as the server runs,
+/// so a game is rated when it finishes instead of when someone looks at it.
+pub async fn finish_games(gm: Arc<game::Manager>, store: Arc<GameStore>, ratings: Arc<Ratings>) {
+    loop {
+        if let Err(e) = get_states(&store, &ratings, &gm, store.get_recent_games(0)).await {
+            println!("Finishing games failed: {}", e);
+        }
+        async_std::task::sleep(FINISH_INTERVAL).await;
+    }
+}
+
+/// The states of the given stored games, asking the game manager about the running ones.
+/// Games that finished since the last call are saved as finished in the store, and rated.
 pub async fn get_states(
-    game_ids: &Vec<(String, u64, SystemTime)>,
+    store: &GameStore,
+    ratings: &Ratings,
     manager: &game::Manager,
+    games: Vec<GameRecord>,
 ) -> Result<Vec<GameState>, String> {
     let mut states = Vec::new();
-    let gss = join_all(
-        game_ids
-            .iter()
-            .cloned()
-            .map(|(name, id, time)| manager.get_state(id).map(move |f| (f, name, time))),
-    )
+    let gss = join_all(games.into_iter().map(|game| async move {
+        let gs = match game.status {
+            GameStatus::Running { manager_id } => manager.get_state(manager_id).await,
+            _ => None,
+        };
+        (gs, game)
+    }))
     .await;
 
-    for (gs, name, time) in gss {
-        if let Some(state) = gs {
-            match state {
-                Ok((state, conns)) => {
-                    let players: Vec<PlayerStatus> =
-                        conns.iter().cloned().map(|x| x.into()).collect();
-                    let connected = players.iter().filter(|x| x.connected).count();
-
-                    states.push(GameState::Playing {
-                        name: name,
-                        total: players.len(),
-                        players,
-                        connected,
-                        map: String::new(),
-                        state,
-                        time,
-                    });
-                }
-                Err(value) => {
-                    let state: FinishedState = serde_json::from_value(value).expect("Shit failed");
-                    states.push(state.into());
-                }
+    for (gs, mut game) in gss {
+        match gs {
+            Some(Ok((state, conns))) => {
+                let players: Vec<PlayerStatus> =
+                    conns.iter().cloned().map(|x| x.into()).collect();
+                let connected = players.iter().filter(|x| x.connected).count();
+
+                states.push(GameState::Playing {
+                    name: game.name,
+                    total: players.len(),
+                    players,
+                    connected,
+                    map: game.map,
+                    file: game.file,
+                    state,
+                    time: game.time,
+                });
+            }
+            Some(Err(value)) => {
+                let state: FinishedState = serde_json::from_value(value).expect("Shit failed");
+                game.status = GameStatus::Finished {
+                    state: state.clone(),
+                };
+                ratings
+                    .rate(&game.id, &state)
+                    .map_err(|_| "IO error".to_string())?;
+                store.save(game).map_err(|_| "IO error".to_string())?;
+                states.push(state.into());
             }
+            None => states.push(game.into()),
         }
     }
 
@@ -1,5 +1,8 @@
 use serde::Deserialize;
 
+use crate::util::*;
+
+use rocket::http::Status;
 use rocket::Route;
 use rocket_contrib::json::Json;
 use rocket_contrib::templates::Template;
@@ -18,21 +21,25 @@ struct MapReq {
 }
 
 /// Post route to create a map.
+/// Maps that cannot be played are refused.
 #[post("/maps", data = "<map_req>")]
-async fn map_post(map_req: Json<MapReq>) -> Result<String, String> {
+async fn map_post(map_req: Json<MapReq>) -> Result<String, JsonError> {
     let MapReq { name, map } = map_req.into_inner();
 
+    map.validate()
+        .map_err(|e| json_error(Status::BadRequest, e))?;
+
     let path: PathBuf = PathBuf::from(format!("maps/{}.json", name));
     if path.exists() {
-        return Err("File already exists!".into());
+        return Err(json_error(Status::Conflict, "File already exists!"));
     }
 
     let mut file = fs::File::create(path)
         .await
-        .map_err(|_| "IO error".to_string())?;
+        .map_err(|_| json_error(Status::InternalServerError, "IO error"))?;
     file.write_all(&serde_json::to_vec_pretty(&map).unwrap())
         .await
-        .map_err(|_| "IO error".to_string())?;
+        .map_err(|_| json_error(Status::InternalServerError, "IO error"))?;
 
     Ok("ok".into())
 }
@@ -1,21 +1,29 @@
-use crate::planetwars::FinishedState;
+use crate::planetwars::{pw_delta, pw_perspective};
+use crate::ratings::Ratings;
+use crate::store::GameStore;
 use crate::util::*;
 
+use rocket::http::Status;
 use rocket::response::NamedFile;
-use rocket::Route;
+use rocket::{Route, State};
 use rocket_contrib::templates::Template;
 
-use async_std::fs;
-use async_std::io::BufReader;
-use async_std::prelude::*;
+use mozaic::modules::game;
 
-use futures::stream::StreamExt;
+use async_std::{fs, task};
 
+use std::cmp::Reverse;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+mod api;
+mod bots;
 mod info;
 mod lobby;
 mod maps;
+mod tournaments;
+pub use lobby::finish_games;
+pub use tournaments::run_tournaments;
 
 /// Handles all files located in the static folder
 #[get("/<file..>", rank = 6)]
@@ -29,6 +37,24 @@ async fn game_get(loc: String) -> Option<NamedFile> {
     NamedFile::open(Path::new("games/").join(loc)).await.ok()
 }
 
+/// Handles game files as one player saw the game, with the messages that player received.
+#[get("/games/<loc>?<player>")]
+async fn game_perspective_get(loc: String, player: usize) -> Result<String, JsonError> {
+    let content = fs::read(Path::new("games/").join(&loc))
+        .await
+        .map_err(|_| json_error(Status::NotFound, format!("Game {} does not exist", loc)))?;
+
+    // Decompressing and replaying a whole game takes a while
+    task::spawn_blocking(move || {
+        let lines = pw_delta::decode_log(&content)
+            .map_err(|e| json_error(Status::BadRequest, e.to_string()))?;
+        pw_perspective::perspective(lines, player)
+            .map(|lines| lines.join("\n"))
+            .map_err(|e| json_error(Status::BadRequest, e.to_string()))
+    })
+    .await
+}
+
 /// Routes the index page, rendering the index Template.
 #[get("/")]
 async fn index() -> Template {
@@ -50,17 +76,44 @@ async fn debug_get() -> Result<Template, String> {
     Ok(Template::render("debug", &context))
 }
 
+/// Number of played games the visualizer lets you choose from.
+const VISUALIZER_GAMES: usize = 100;
+
 /// Routes the visualizer page, rendering the visualizer Template.
+/// Only the most recent played games are listed.
 #[get("/visualizer")]
-async fn visualizer_get() -> Template {
-    let mut game_options: Vec<GameState> = get_played_games().await;
-    game_options.sort();
+async fn visualizer_get(
+    gm: State<'_, Arc<game::Manager>>,
+    store: State<'_, Arc<GameStore>>,
+    ratings: State<'_, Arc<Ratings>>,
+) -> Result<Template, String> {
+    let recent = store.get_recent_games(VISUALIZER_GAMES);
+    let mut game_options: Vec<GameState> = lobby::get_states(&store, &ratings, &gm, recent)
+        .await?
+        .into_iter()
+        .filter(|game| match game {
+            GameState::Finished { .. } => true,
+            _ => false,
+        })
+        .collect();
+    game_options.sort_by_key(|game| Reverse(game.time()));
+    game_options.truncate(VISUALIZER_GAMES);
 
     let context = Context::new_with(
         "Visualizer",
         json!({"games": game_options, "colours": COLOURS}),
     );
-    Template::render("visualizer", &context)
+    Ok(Template::render("visualizer", &context))
+}
+
+/// Routes the leaderboard page, rendering the leaderboard Template.
+#[get("/leaderboard")]
+async fn leaderboard_get(ratings: State<'_, Arc<Ratings>>) -> Template {
+    let context = Context::new_with(
+        "Leaderboard",
+        json!({ "standings": ratings.leaderboard() }),
+    );
+    Template::render("leaderboard", &context)
 }
 
 /// Fuels all routes
@@ -69,34 +122,16 @@ pub fn fuel(routes: &mut Vec<Route>) {
         files,
         index,
         game_get,
+        game_perspective_get,
         builder_get,
         visualizer_get,
+        leaderboard_get,
         debug_get
     ]);
+    api::fuel(routes);
+    bots::fuel(routes);
     lobby::fuel(routes);
     maps::fuel(routes);
+    tournaments::fuel(routes);
     info::fuel(routes);
 }
-
-/// Reads games.json
-/// File that represents all played games
-/// Ready to be visualized
-async fn get_played_games() -> Vec<GameState> {
-    match fs::File::open("games/games.json").await {
-        Ok(file) => {
-            let file = BufReader::new(file);
-            file.lines()
-                .filter_map(move |maybe| {
-                    async {
-                        maybe
-                            .ok()
-                            .and_then(|line| serde_json::from_str::<FinishedState>(&line).ok())
-                    }
-                })
-                .map(|state| state.into())
-                .collect()
-                .await
-        }
-        Err(_) => Vec::new(),
-    }
-}
@@ -0,0 +1,415 @@
+use crate::hosted::HostedBots;
+use crate::planetwars;
+use crate::planetwars::pw_feed::Feeds;
+use crate::ratings::Ratings;
+use crate::store::{GameStatus, GameStore};
+use crate::tournament::{Format, GameResult, MatchRules, Standing, Tournament, Tournaments};
+use crate::util::*;
+
+use super::lobby::{get_states, start_game};
+
+use rocket::http::Status;
+use rocket::{Route, State};
+use rocket_contrib::json::Json;
+use rocket_contrib::templates::Template;
+
+use mozaic::modules::game;
+
+use futures::executor::ThreadPool;
+
+use serde_json::Value;
+
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The json body to create a tournament.
+#[derive(Deserialize, Debug)]
+struct TournamentReq {
+    name: String,
+    format: Format,
+    /// Names of the bots, in seeding order.
+    bots: Vec<String>,
+    /// Names of the bots the server runs, they have to be uploaded.
+    #[serde(default)]
+    hosted: Vec<String>,
+    /// Names of the maps in the maps folder.
+    maps: Vec<String>,
+    #[serde(flatten)]
+    rules: MatchRules,
+}
+
+/// A tournament with its current standings.
+#[derive(Serialize)]
+struct TournamentRes {
+    #[serde(flatten)]
+    tournament: Tournament,
+    standings: Vec<Standing>,
+    finished: bool,
+}
+
+impl From<Tournament> for TournamentRes {
+    fn from(tournament: Tournament) -> Self {
+        TournamentRes {
+            standings: tournament.standings(),
+            finished: tournament.is_finished(),
+            tournament: tournament.without_secrets(),
+        }
+    }
+}
+
+/// A created tournament, with the secret token of every bot.
+/// Only this response has them, a bot needs its token to get the keys of its matches.
+#[derive(Serialize)]
+struct CreatedRes {
+    #[serde(flatten)]
+    tournament: TournamentRes,
+    tokens: HashMap<String, String>,
+}
+
+/// A match a bot still has to play, with the key to connect with.
+#[derive(Serialize)]
+struct BotMatch {
+    game: String,
+    key: u64,
+    map: String,
+    opponent: String,
+}
+
+/// Creates a tournament and starts the games of its first round.
+/// The tokens of the bots are only returned here.
+#[post("/tournaments", data = "<req>")]
+async fn tournament_post(
+    req: Json<TournamentReq>,
+    tp: State<'_, ThreadPool>,
+    gm: State<'_, Arc<game::Manager>>,
+    store: State<'_, Arc<GameStore>>,
+    ratings: State<'_, Arc<Ratings>>,
+    feeds: State<'_, Arc<Feeds>>,
+    hosted: State<'_, Arc<HostedBots>>,
+    tournaments: State<'_, Arc<Tournaments>>,
+) -> Result<Json<CreatedRes>, JsonError> {
+    let TournamentReq {
+        name,
+        format,
+        bots,
+        hosted: hosted_bots,
+        maps,
+        rules,
+    } = req.into_inner();
+
+    if let Some(name) = hosted_bots
+        .iter()
+        .find(|name| hosted.get_bot(name).is_none())
+    {
+        return Err(json_error(
+            Status::BadRequest,
+            format!("Bot {} is not uploaded", name),
+        ));
+    }
+
+    if let Some(policy) = &rules.disqualify {
+        policy
+            .validate()
+            .map_err(|e| json_error(Status::BadRequest, e))?;
+    }
+
+    for map in maps.iter() {
+        if map.contains("..") {
+            return Err(json_error(Status::BadRequest, format!("Invalid map {}", map)));
+        }
+        let config = map_config(map, &rules);
+        config
+            .validate()
+            .map_err(|e| json_error(Status::BadRequest, e))?;
+        config
+            .create_game(2)
+            .map_err(|e| json_error(Status::BadRequest, e))?;
+    }
+
+    let tournament = Tournament::new(generate_id(), name, format, bots, hosted_bots, maps, rules)
+        .map_err(|e| json_error(Status::BadRequest, e))?;
+    let id = tournament.id.clone();
+    tournaments
+        .add(tournament)
+        .map_err(|e| json_error(Status::InternalServerError, e.to_string()))?;
+
+    advance(&tournaments, &tp, &gm, &store, &ratings, &feeds, &hosted).await?;
+    let tournament = tournaments.get_tournament(&id).unwrap();
+    Ok(Json(CreatedRes {
+        tokens: tournament
+            .bots
+            .iter()
+            .cloned()
+            .zip(tournament.tokens.iter().cloned())
+            .collect(),
+        tournament: tournament.into(),
+    }))
+}
+
+/// Routes the tournaments page, rendering the tournaments Template.
+#[get("/tournaments")]
+async fn tournaments_get(tournaments: State<'_, Arc<Tournaments>>) -> Template {
+    let tournaments: Vec<TournamentRes> = tournaments
+        .get_tournaments()
+        .into_iter()
+        .rev()
+        .map(TournamentRes::from)
+        .collect();
+    let context = Context::new_with("Tournaments", json!({ "tournaments": tournaments }));
+    Template::render("tournaments", &context)
+}
+
+/// Routes the page of one tournament, with its standings and matches.
+#[get("/tournaments/<id>")]
+async fn tournament_get(
+    id: String,
+    tournaments: State<'_, Arc<Tournaments>>,
+) -> Result<Template, JsonError> {
+    let tournament = get_tournament(&tournaments, &id)?;
+    let context = Context::new_with(
+        "Tournaments",
+        json!({
+            "name": tournament.name,
+            "finished": tournament.is_finished(),
+            "standings": tournament.standings(),
+            "rounds": named_rounds(&tournament),
+        }),
+    );
+    Ok(Template::render("tournament", &context))
+}
+
+/// All tournaments, most recent first.
+#[get("/api/v1/tournaments")]
+async fn tournaments_api_get(tournaments: State<'_, Arc<Tournaments>>) -> Json<Vec<TournamentRes>> {
+    Json(
+        tournaments
+            .get_tournaments()
+            .into_iter()
+            .rev()
+            .map(TournamentRes::from)
+            .collect(),
+    )
+}
+
+/// One tournament, by its id.
+#[get("/api/v1/tournaments/<id>")]
+async fn tournament_api_get(
+    id: String,
+    tournaments: State<'_, Arc<Tournaments>>,
+) -> Result<Json<TournamentRes>, JsonError> {
+    Ok(Json(get_tournament(&tournaments, &id)?.into()))
+}
+
+/// The matches a bot still has to play in the current round, bots poll this to get their keys.
+/// Only the bot itself gets its keys, with the token it got when the tournament was created.
+#[get("/api/v1/tournaments/<id>/bots/<bot>?<token>")]
+async fn bot_matches_get(
+    id: String,
+    bot: String,
+    token: String,
+    tournaments: State<'_, Arc<Tournaments>>,
+) -> Result<Json<Vec<BotMatch>>, JsonError> {
+    let tournament = get_tournament(&tournaments, &id)?;
+    let index = tournament
+        .bots
+        .iter()
+        .position(|b| *b == bot)
+        .ok_or_else(|| json_error(Status::NotFound, format!("Bot {} is not registered", bot)))?;
+    if !tournament.has_token(index, &token) {
+        return Err(json_error(
+            Status::Forbidden,
+            format!("The token of bot {} is wrong", bot),
+        ));
+    }
+
+    let matches = tournament
+        .rounds
+        .last()
+        .into_iter()
+        .flat_map(|round| round.matches.iter())
+        .filter(|m| m.winners.is_none())
+        .filter_map(|m| {
+            let player = m.bots.iter().position(|&b| b == index)?;
+            Some(BotMatch {
+                game: m.game.clone()?,
+                key: *m.keys.get(player)?,
+                map: m.map.clone(),
+                opponent: tournament.bots[m.bots[1 - player]].clone(),
+            })
+        })
+        .collect();
+
+    Ok(Json(matches))
+}
+
+/// How often the tournaments are advanced in the background.
+const ADVANCE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Advances the tournaments every few seconds, as long as the server runs.
+pub async fn run_tournaments(
+    tournaments: Arc<Tournaments>,
+    pool: ThreadPool,
+    gm: Arc<game::Manager>,
+    store: Arc<GameStore>,
+    ratings: Arc<Ratings>,
+    feeds: Arc<Feeds>,
+    hosted: Arc<HostedBots>,
+) {
+    loop {
+        if let Err(e) = advance(&tournaments, &pool, &gm, &store, &ratings, &feeds, &hosted).await {
+            println!("Advancing the tournaments failed: {:?}", e);
+        }
+        async_std::task::sleep(ADVANCE_INTERVAL).await;
+    }
+}
+
+/// Records the results of finished tournament games, schedules new rounds
+/// and starts the games of matches that have none yet.
+/// The bots registered as hosted are run by the server.
+/// Matches that fail to start are logged and tried again the next time.
+async fn advance(
+    tournaments: &Tournaments,
+    pool: &ThreadPool,
+    gm: &game::Manager,
+    store: &GameStore,
+    ratings: &Ratings,
+    feeds: &Feeds,
+    hosted: &HostedBots,
+) -> Result<(), JsonError> {
+    let _starting = tournaments.starting.lock().await;
+
+    get_states(store, ratings, gm, store.get_recent_games(0))
+        .await
+        .map_err(|e| json_error(Status::InternalServerError, e))?;
+    let games: HashMap<String, GameStatus> = store
+        .get_games()
+        .into_iter()
+        .map(|game| (game.id, game.status))
+        .collect();
+
+    tournaments
+        .update(|id| match games.get(id) {
+            Some(GameStatus::Running { .. }) => GameResult::Running,
+            Some(GameStatus::Finished { state }) => GameResult::Finished(state.winners.clone()),
+            _ => GameResult::Interrupted,
+        })
+        .map_err(|e| json_error(Status::InternalServerError, e.to_string()))?;
+
+    for tournament in tournaments.get_tournaments() {
+        for (round, index) in tournament.unstarted() {
+            let m = &tournament.rounds[round].matches[index];
+            let name = format!(
+                "{} round {}: {} vs {}",
+                tournament.name,
+                round + 1,
+                tournament.bots[m.bots[0]],
+                tournament.bots[m.bots[1]]
+            );
+
+            let game = match start_game(
+                pool.clone(),
+                gm,
+                store,
+                feeds,
+                map_config(&m.map, &tournament.rules),
+                m.bots.len() as u64,
+                Vec::new(),
+                &name,
+            )
+            .await
+            {
+                Ok(game) => game,
+                Err(e) => {
+                    println!("Starting {} failed: {:?}", name, e);
+                    continue;
+                }
+            };
+
+            for (&bot, &key) in m.bots.iter().zip(game.players.iter()) {
+                let bot = &tournament.bots[bot];
+                if tournament.hosted.contains(bot) {
+                    if let Err(e) = hosted.launch(bot, key) {
+                        println!("Launching bot {} failed: {}", bot, e);
+                    }
+                }
+            }
+
+            if let Err(e) =
+                tournaments.start_match(&tournament.id, (round, index), game.id, game.players)
+            {
+                println!("Saving the game of {} failed: {}", name, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The rounds of a tournament with bot names instead of indices, most recent first.
+fn named_rounds(tournament: &Tournament) -> Vec<Value> {
+    let names = |bots: &[usize]| -> Vec<&str> {
+        bots.iter().map(|&b| tournament.bots[b].as_str()).collect()
+    };
+
+    tournament
+        .rounds
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, round)| {
+            json!({
+                "number": i + 1,
+                "byes": names(&round.byes),
+                "matches": round.matches.iter().map(|m| json!({
+                    "bots": names(&m.bots),
+                    "map": m.map,
+                    "game": m.game,
+                    "finished": m.winners.is_some(),
+                    "winners": m.winners.as_ref().map(|w| names(w)),
+                })).collect::<Vec<_>>(),
+            })
+        })
+        .collect()
+}
+
+fn map_config(map: &str, rules: &MatchRules) -> planetwars::Config {
+    planetwars::Config {
+        map_file: format!("maps/{}.json", map),
+        max_turns: rules.max_turns,
+        combat: rules.combat,
+        victory: rules.victory,
+        vision: rules.vision,
+        turn_timeout: rules.turn_timeout,
+        time_bank: rules.time_bank,
+        disqualify: rules.disqualify.clone(),
+    }
+}
+
+fn get_tournament(tournaments: &Tournaments, id: &str) -> Result<Tournament, JsonError> {
+    tournaments
+        .get_tournament(id)
+        .ok_or_else(|| json_error(Status::NotFound, format!("Tournament {} does not exist", id)))
+}
+
+/// Generate random ID for a tournament
+fn generate_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(10)
+        .map(|x| x as char)
+        .collect()
+}
+
+/// Fuels the tournament routes
+pub fn fuel(routes: &mut Vec<Route>) {
+    routes.extend(routes![
+        tournament_post,
+        tournaments_get,
+        tournament_get,
+        tournaments_api_get,
+        tournament_api_get,
+        bot_matches_get
+    ]);
+}
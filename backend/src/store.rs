@@ -0,0 +1,208 @@
+use crate::planetwars::{Config, FinishedState};
+
+use serde::de::DeserializeOwned;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Outdated lines allowed in the store file before it is compacted.
+const COMPACT_SLACK: usize = 100;
+
+/// Status of a stored game.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum GameStatus {
+    /// The game manager is running the game, with this id.
+    Running { manager_id: u64 },
+    Finished { state: FinishedState },
+    /// The server stopped while the game was running.
+    Interrupted,
+}
+
+/// A game, as it is persisted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameRecord {
+    /// Name of the log file, without extension.
+    pub id: String,
+    pub name: String,
+    pub map: String,
+    pub players: usize,
+    /// Games imported from the finished games file have no config.
+    pub config: Option<Config>,
+    /// Log file, relative to the games folder.
+    pub file: String,
+    pub time: SystemTime,
+    pub status: GameStatus,
+}
+
+impl GameRecord {
+    fn from_finished(state: FinishedState) -> Self {
+        GameRecord {
+            id: file_id(&state.file).to_string(),
+            name: state.name.clone(),
+            map: state.map.clone(),
+            players: state.players.len(),
+            config: None,
+            file: state.file.clone(),
+            time: state.time,
+            status: GameStatus::Finished { state },
+        }
+    }
+}
+
+/// The id of a game is the name of its log file, without extension.
+pub fn file_id(file: &str) -> &str {
+    file.trim_end_matches(".json")
+}
+
+/// Persistent store of all games, surviving server restarts.
+/// Games are appended to the store file as json lines, the last line of a game is its current version.
+/// The file is compacted when the store is opened, and when too many lines are outdated.
+pub struct GameStore {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    path: PathBuf,
+    file: File,
+    games: Vec<GameRecord>,
+    lines: usize,
+}
+
+impl GameStore {
+    /// Opens the store, importing the finished games the game manager wrote to `finished`.
+    /// Games that were still running when the server stopped are marked as interrupted.
+    pub fn open<P: AsRef<Path>, F: AsRef<Path>>(path: P, finished: F) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut games = Vec::new();
+        for game in read_lines::<GameRecord>(&path)? {
+            upsert(&mut games, game);
+        }
+
+        for state in read_lines::<FinishedState>(finished.as_ref())? {
+            match games
+                .iter_mut()
+                .find(|game| game.id == file_id(&state.file))
+            {
+                Some(game) => game.status = GameStatus::Finished { state },
+                None => games.push(GameRecord::from_finished(state)),
+            }
+        }
+
+        for game in games.iter_mut() {
+            if let GameStatus::Running { .. } = game.status {
+                game.status = GameStatus::Interrupted;
+            }
+        }
+
+        let file = write_compacted(&path, &games)?;
+
+        Ok(GameStore {
+            inner: Mutex::new(Inner {
+                path,
+                file,
+                lines: games.len(),
+                games,
+            }),
+        })
+    }
+
+    pub fn get_games(&self) -> Vec<GameRecord> {
+        self.inner.lock().unwrap().games.clone()
+    }
+
+    pub fn get_game(&self, id: &str) -> Option<GameRecord> {
+        let inner = self.inner.lock().unwrap();
+        inner.games.iter().find(|game| game.id == id).cloned()
+    }
+
+    /// All running games, and the given number of most recent games that are not running.
+    pub fn get_recent_games(&self, recent: usize) -> Vec<GameRecord> {
+        let (mut running, others): (Vec<_>, Vec<_>) = self
+            .newest_first()
+            .into_iter()
+            .partition(|game| matches!(game.status, GameStatus::Running { .. }));
+        running.extend(others.into_iter().take(recent));
+        running
+    }
+
+    /// One page of all games, most recent first, the first page is page 1.
+    pub fn get_page(&self, page: usize, per_page: usize) -> Vec<GameRecord> {
+        self.newest_first()
+            .into_iter()
+            .skip(page.saturating_sub(1).saturating_mul(per_page))
+            .take(per_page)
+            .collect()
+    }
+
+    fn newest_first(&self) -> Vec<GameRecord> {
+        let mut games = self.get_games();
+        games.sort_by(|a, b| b.time.cmp(&a.time));
+        games
+    }
+
+    /// Adds a new game, or updates the game with the same id.
+    pub fn save(&self, game: GameRecord) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+
+        write!(inner.file, "{}\n", serde_json::to_string(&game).unwrap())?;
+        inner.lines += 1;
+        upsert(&mut inner.games, game);
+
+        if inner.lines > 2 * inner.games.len() + COMPACT_SLACK {
+            inner.file = write_compacted(&inner.path, &inner.games)?;
+            inner.lines = inner.games.len();
+        }
+
+        Ok(())
+    }
+}
+
+fn upsert(games: &mut Vec<GameRecord>, game: GameRecord) {
+    match games.iter_mut().find(|other| other.id == game.id) {
+        Some(other) => *other = game,
+        None => games.push(game),
+    }
+}
+
+/// Reads a json lines file, skipping lines that cannot be parsed.
+/// A file that does not exist yet is empty.
+fn read_lines<T: DeserializeOwned>(path: &Path) -> io::Result<Vec<T>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut out = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(value) = serde_json::from_str(&line?) {
+            out.push(value);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Replaces the store file with one line per game, returning the file to append to.
+fn write_compacted(path: &Path, games: &[GameRecord]) -> io::Result<File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = File::create(&tmp)?;
+        for game in games {
+            write!(file, "{}\n", serde_json::to_string(game).unwrap())?;
+        }
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+
+    OpenOptions::new().append(true).open(path)
+}
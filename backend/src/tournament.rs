@@ -0,0 +1,844 @@
+use crate::hosted::generate_token;
+use crate::planetwars::pw_config::default_turn_timeout;
+use crate::planetwars::pw_rules::{CombatRule, Victory};
+use crate::planetwars::pw_stats::DisqualifyPolicy;
+
+use serde::{Deserialize, Deserializer};
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// How the matches of a tournament are scheduled, all matches are 2 player games.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum Format {
+    /// Every bot plays every other bot once.
+    RoundRobin,
+    /// This many rounds, bots with the same score play each other, avoiding rematches.
+    Swiss(usize),
+    /// Single elimination in a bracket where the best seeds meet as late as possible,
+    /// on a draw the better seed advances.
+    Elimination,
+}
+
+/// The rules of every match in a tournament.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatchRules {
+    pub max_turns: u64,
+    #[serde(default)]
+    pub combat: CombatRule,
+    /// A victory condition that ranks at max turns avoids draws.
+    #[serde(default)]
+    pub victory: Victory,
+    /// Vision radius of the bots, for fog of war.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision: Option<f64>,
+    /// In milliseconds
+    #[serde(default = "default_turn_timeout")]
+    pub turn_timeout: u64,
+    /// In milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_bank: Option<u64>,
+    /// Disqualifying crashed bots keeps the tournament from waiting on them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disqualify: Option<DisqualifyPolicy>,
+}
+
+/// Why a tournament cannot be created.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum TournamentError {
+    NotEnoughBots,
+    DuplicateBot(String),
+    /// A hosted bot that is not registered in the tournament.
+    UnknownHosted(String),
+    NoMaps,
+    NoRounds,
+}
+
+impl fmt::Display for TournamentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TournamentError::NotEnoughBots => write!(f, "a tournament needs at least 2 bots"),
+            TournamentError::DuplicateBot(name) => write!(f, "bot {} is registered twice", name),
+            TournamentError::UnknownHosted(name) => {
+                write!(f, "hosted bot {} is not registered", name)
+            }
+            TournamentError::NoMaps => write!(f, "a tournament needs at least 1 map"),
+            TournamentError::NoRounds => write!(f, "a swiss tournament needs at least 1 round"),
+        }
+    }
+}
+
+/// The status of the game of a match, as known by the game store.
+pub enum GameResult {
+    Running,
+    /// Player ids of the winners.
+    Finished(Vec<u64>),
+    /// The game will never finish, so the match is played again.
+    Interrupted,
+}
+
+/// A 2 player game between bots of the tournament.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Match {
+    /// Indices of the bots in the tournament, the first bot is player 0.
+    pub bots: Vec<usize>,
+    pub map: String,
+    /// Id of the game, once it is started.
+    pub game: Option<String>,
+    /// Connection keys, in the same order as the bots.
+    /// A bot only gets its key with its token, they are never shown to others.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<u64>,
+    /// Indices of the winning bots in the tournament, once the game finished.
+    pub winners: Option<Vec<usize>>,
+}
+
+impl Match {
+    fn new(bots: Vec<usize>, map: &str) -> Self {
+        Match {
+            bots,
+            map: map.to_string(),
+            game: None,
+            keys: Vec::new(),
+            winners: None,
+        }
+    }
+
+    /// A match with all or none of the bots winning is a draw.
+    fn is_draw(&self) -> bool {
+        self.winners
+            .as_ref()
+            .map_or(false, |w| w.is_empty() || w.len() == self.bots.len())
+    }
+
+    /// The bot that advances in an elimination tournament, the best seed after a draw.
+    fn advancing(&self) -> Option<usize> {
+        let winners = self.winners.as_ref()?;
+        if self.is_draw() {
+            self.bots.iter().min().cloned()
+        } else {
+            winners.first().cloned()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Round {
+    pub matches: Vec<Match>,
+    /// The bots that sit this round out: the one left over when there is an odd number of bots,
+    /// or the best seeds in the first round of an elimination bracket with empty places.
+    #[serde(default, alias = "bye", deserialize_with = "deserialize_byes")]
+    pub byes: Vec<usize>,
+}
+
+/// Reads the byes of a round, also the single `bye` of rounds from before there could be more.
+fn deserialize_byes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<usize>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Byes {
+        One(Option<usize>),
+        Many(Vec<usize>),
+    }
+
+    Ok(match Byes::deserialize(deserializer)? {
+        Byes::One(bye) => bye.into_iter().collect(),
+        Byes::Many(byes) => byes,
+    })
+}
+
+impl Round {
+    fn is_done(&self) -> bool {
+        self.matches.iter().all(|m| m.winners.is_some())
+    }
+}
+
+/// A row of the standings.
+#[derive(Serialize, Debug, Clone)]
+pub struct Standing {
+    pub bot: String,
+    /// A win or a bye is worth 1 point, a draw half a point.
+    pub points: f64,
+    pub wins: u64,
+    pub draws: u64,
+    pub losses: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tournament {
+    pub id: String,
+    pub name: String,
+    pub format: Format,
+    /// The registered bots, in seeding order.
+    pub bots: Vec<String>,
+    /// The registered bots the server runs itself, they are uploaded bots.
+    #[serde(default)]
+    pub hosted: Vec<String>,
+    /// Maps in rotation, one per round.
+    pub maps: Vec<String>,
+    #[serde(flatten)]
+    pub rules: MatchRules,
+    pub rounds: Vec<Round>,
+    /// Secret tokens of the bots, in seeding order, a bot needs its token to get its keys.
+    /// Empty for tournaments created before there were tokens.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<String>,
+}
+
+impl Tournament {
+    /// Creates the tournament and schedules its first round.
+    pub fn new(
+        id: String,
+        name: String,
+        format: Format,
+        bots: Vec<String>,
+        hosted: Vec<String>,
+        maps: Vec<String>,
+        rules: MatchRules,
+    ) -> Result<Self, TournamentError> {
+        if bots.len() < 2 {
+            return Err(TournamentError::NotEnoughBots);
+        }
+        let mut names = BTreeSet::new();
+        for bot in bots.iter() {
+            if !names.insert(bot) {
+                return Err(TournamentError::DuplicateBot(bot.clone()));
+            }
+        }
+        if let Some(name) = hosted.iter().find(|name| !names.contains(name)) {
+            return Err(TournamentError::UnknownHosted(name.clone()));
+        }
+        if maps.is_empty() {
+            return Err(TournamentError::NoMaps);
+        }
+        if format == Format::Swiss(0) {
+            return Err(TournamentError::NoRounds);
+        }
+
+        let tokens = bots.iter().map(|_| generate_token()).collect();
+        let mut tournament = Tournament {
+            id,
+            name,
+            format,
+            bots,
+            hosted,
+            maps,
+            rules,
+            rounds: Vec::new(),
+            tokens,
+        };
+        tournament.schedule_next_round();
+        Ok(tournament)
+    }
+
+    /// Whether the token is the one the bot was registered with.
+    pub fn has_token(&self, bot: usize, token: &str) -> bool {
+        self.tokens.get(bot).map_or(false, |t| t == token)
+    }
+
+    /// The tournament without its tokens and keys, as it is shown to everyone.
+    pub fn without_secrets(mut self) -> Self {
+        self.tokens.clear();
+        for round in self.rounds.iter_mut() {
+            for m in round.matches.iter_mut() {
+                m.keys.clear();
+            }
+        }
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.rounds.last().map_or(true, Round::is_done) && self.pairings().is_none()
+    }
+
+    /// Records the results of started games, using `result` to look up games by id.
+    /// When the current round is done, the next one is scheduled.
+    /// Returns whether anything changed.
+    pub fn update<F: Fn(&str) -> GameResult>(&mut self, result: F) -> bool {
+        let mut changed = false;
+
+        if let Some(round) = self.rounds.last_mut() {
+            for m in round.matches.iter_mut().filter(|m| m.winners.is_none()) {
+                let status = match &m.game {
+                    Some(game) => result(game),
+                    None => continue,
+                };
+
+                match status {
+                    GameResult::Running => {}
+                    GameResult::Finished(winners) => {
+                        let bots = &m.bots;
+                        m.winners = Some(
+                            winners
+                                .iter()
+                                .filter_map(|&id| bots.get(id as usize).cloned())
+                                .collect(),
+                        );
+                        changed = true;
+                    }
+                    GameResult::Interrupted => {
+                        m.game = None;
+                        m.keys.clear();
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        if self.rounds.last().map_or(false, Round::is_done) && self.schedule_next_round() {
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Matches of the current round that have no game yet, as (round, match) indices.
+    pub fn unstarted(&self) -> Vec<(usize, usize)> {
+        match self.rounds.last() {
+            Some(round) => round
+                .matches
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| m.game.is_none())
+                .map(|(i, _)| (self.rounds.len() - 1, i))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn standings(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = self
+            .bots
+            .iter()
+            .map(|bot| Standing {
+                bot: bot.clone(),
+                points: 0.0,
+                wins: 0,
+                draws: 0,
+                losses: 0,
+            })
+            .collect();
+
+        for round in self.rounds.iter() {
+            for &bye in round.byes.iter() {
+                standings[bye].points += 1.0;
+            }
+
+            for m in round.matches.iter() {
+                let winners = match &m.winners {
+                    Some(winners) => winners,
+                    None => continue,
+                };
+
+                for &bot in m.bots.iter() {
+                    let standing = &mut standings[bot];
+                    if m.is_draw() {
+                        standing.points += 0.5;
+                        standing.draws += 1;
+                    } else if winners.contains(&bot) {
+                        standing.points += 1.0;
+                        standing.wins += 1;
+                    } else {
+                        standing.losses += 1;
+                    }
+                }
+            }
+        }
+
+        standings.sort_by(|a, b| b.points.total_cmp(&a.points));
+        standings
+    }
+
+    fn schedule_next_round(&mut self) -> bool {
+        let (pairs, byes) = match self.pairings() {
+            Some(pairings) => pairings,
+            None => return false,
+        };
+
+        let map = &self.maps[self.rounds.len() % self.maps.len()];
+        self.rounds.push(Round {
+            matches: pairs
+                .into_iter()
+                .map(|(a, b)| Match::new(vec![a, b], map))
+                .collect(),
+            byes,
+        });
+        true
+    }
+
+    /// The pairings and byes of the next round, or None when the tournament is over.
+    fn pairings(&self) -> Option<(Vec<(usize, usize)>, Vec<usize>)> {
+        let (pairs, bye) = match self.format {
+            Format::RoundRobin => self.round_robin_pairings()?,
+            Format::Swiss(rounds) => {
+                if self.rounds.len() >= rounds {
+                    return None;
+                }
+                self.swiss_pairings()
+            }
+            Format::Elimination => return self.elimination_pairings(),
+        };
+        Some((pairs, bye.into_iter().collect()))
+    }
+
+    /// Circle method: the first bot stays in place, the others rotate every round.
+    /// With an odd number of bots, the bot paired with the missing one gets a bye.
+    fn round_robin_pairings(&self) -> Option<(Vec<(usize, usize)>, Option<usize>)> {
+        let n = self.bots.len();
+        let m = n + n % 2;
+        let round = self.rounds.len();
+        if round >= m - 1 {
+            return None;
+        }
+
+        let mut circle: Vec<usize> = (1..m).collect();
+        circle.rotate_right(round % (m - 1));
+        circle.insert(0, 0);
+
+        let mut pairs = Vec::new();
+        let mut bye = None;
+        for i in 0..m / 2 {
+            let (a, b) = (circle[i], circle[m - 1 - i]);
+            if a == n {
+                bye = Some(b);
+            } else if b == n {
+                bye = Some(a);
+            } else {
+                pairs.push((a, b));
+            }
+        }
+
+        Some((pairs, bye))
+    }
+
+    /// Pairs bots in order of their standing, avoiding rematches where possible.
+    /// With an odd number of bots, the lowest ranked bot without a bye gets one.
+    fn swiss_pairings(&self) -> (Vec<(usize, usize)>, Option<usize>) {
+        let standings = self.standings();
+        let mut order: Vec<usize> = standings
+            .iter()
+            .map(|s| self.bots.iter().position(|b| *b == s.bot).unwrap())
+            .collect();
+
+        let mut bye = None;
+        if order.len() % 2 == 1 {
+            let had_bye = |bot: usize| self.rounds.iter().any(|r| r.byes.contains(&bot));
+            let pos = order
+                .iter()
+                .rposition(|&bot| !had_bye(bot))
+                .unwrap_or(order.len() - 1);
+            bye = Some(order.remove(pos));
+        }
+
+        let played = |a: usize, b: usize| {
+            self.rounds.iter().any(|r| {
+                r.matches
+                    .iter()
+                    .any(|m| m.bots.contains(&a) && m.bots.contains(&b))
+            })
+        };
+
+        let mut budget = SWISS_SEARCH_BUDGET;
+        let pairs = pair_without_rematches(&order, &played, &mut budget).unwrap_or_else(|| {
+            // Rematches cannot be avoided, pair the next bot that was not played where possible
+            let mut pairs = Vec::new();
+            while !order.is_empty() {
+                let a = order.remove(0);
+                let pos = order.iter().position(|&b| !played(a, b)).unwrap_or(0);
+                pairs.push((a, order.remove(pos)));
+            }
+            pairs
+        });
+
+        (pairs, bye)
+    }
+
+    /// The first round places the bots in a bracket, the best seeds get a bye
+    /// when the bracket has more places than there are bots.
+    /// Later rounds pair the bots that advanced from neighbouring places of the bracket.
+    fn elimination_pairings(&self) -> Option<(Vec<(usize, usize)>, Vec<usize>)> {
+        let order = bracket(self.bots.len().next_power_of_two());
+
+        let round = match self.rounds.last() {
+            Some(round) => round,
+            None => {
+                let mut pairs = Vec::new();
+                let mut byes = Vec::new();
+                for places in order.chunks(2) {
+                    if places[1] < self.bots.len() {
+                        pairs.push((places[0], places[1]));
+                    } else {
+                        byes.push(places[0]);
+                    }
+                }
+                return Some((pairs, byes));
+            }
+        };
+
+        let mut alive: Vec<usize> = round.matches.iter().filter_map(Match::advancing).collect();
+        alive.extend(round.byes.iter().cloned());
+        if alive.len() < 2 {
+            return None;
+        }
+        alive.sort_by_key(|bot| order.iter().position(|seed| seed == bot));
+
+        // Only tournaments scheduled before there were brackets can have an odd number left
+        let byes = if alive.len() % 2 == 1 {
+            vec![alive.remove(0)]
+        } else {
+            Vec::new()
+        };
+        Some((alive.chunks(2).map(|c| (c[0], c[1])).collect(), byes))
+    }
+}
+
+/// The seeds in the order of their places in an elimination bracket of the given size,
+/// a power of two. The best seeds play the worst ones, and two seeds only meet
+/// once all better seeds in their half of the bracket are out.
+fn bracket(size: usize) -> Vec<usize> {
+    let mut order = vec![0];
+    while order.len() < size {
+        let places = order.len() * 2;
+        order = order
+            .iter()
+            .flat_map(|&seed| vec![seed, places - 1 - seed])
+            .collect();
+    }
+    order
+}
+
+/// Pairings the swiss format tries at most to avoid rematches.
+const SWISS_SEARCH_BUDGET: usize = 10_000;
+
+/// Pairs the bots in order, every bot with the first bot after it that it did not play yet,
+/// backtracking when the bots that are left cannot be paired without rematches.
+/// None when there is no such pairing, or it was not found within the budget.
+fn pair_without_rematches<F: Fn(usize, usize) -> bool>(
+    order: &[usize],
+    played: &F,
+    budget: &mut usize,
+) -> Option<Vec<(usize, usize)>> {
+    let (&a, rest) = match order.split_first() {
+        Some(split) => split,
+        None => return Some(Vec::new()),
+    };
+
+    for (i, &b) in rest.iter().enumerate() {
+        if *budget == 0 {
+            return None;
+        }
+        *budget -= 1;
+        if played(a, b) {
+            continue;
+        }
+
+        let others: Vec<usize> = rest[..i].iter().chain(&rest[i + 1..]).cloned().collect();
+        if let Some(mut pairs) = pair_without_rematches(&others, played, budget) {
+            pairs.insert(0, (a, b));
+            return Some(pairs);
+        }
+    }
+
+    None
+}
+
+/// All tournaments, persisted in a single json file.
+pub struct Tournaments {
+    path: PathBuf,
+    inner: Mutex<Vec<Tournament>>,
+    /// Held while games are being started for tournaments, so no match is started twice.
+    pub starting: async_std::sync::Mutex<()>,
+}
+
+impl Tournaments {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let tournaments = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(file)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Tournaments {
+            path,
+            inner: Mutex::new(tournaments),
+            starting: async_std::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn get_tournaments(&self) -> Vec<Tournament> {
+        self.inner.lock().unwrap().clone()
+    }
+
+    pub fn get_tournament(&self, id: &str) -> Option<Tournament> {
+        self.inner
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|t| t.id == id)
+            .cloned()
+    }
+
+    pub fn add(&self, tournament: Tournament) -> io::Result<()> {
+        let mut tournaments = self.inner.lock().unwrap();
+        tournaments.push(tournament);
+        self.save(&tournaments)
+    }
+
+    /// Updates all tournaments with the results of their games.
+    pub fn update<F: Fn(&str) -> GameResult>(&self, result: F) -> io::Result<()> {
+        let mut tournaments = self.inner.lock().unwrap();
+        let mut changed = false;
+        for tournament in tournaments.iter_mut() {
+            changed |= tournament.update(&result);
+        }
+
+        if changed {
+            self.save(&tournaments)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the started game of a match.
+    pub fn start_match(
+        &self,
+        id: &str,
+        (round, index): (usize, usize),
+        game: String,
+        keys: Vec<u64>,
+    ) -> io::Result<()> {
+        let mut tournaments = self.inner.lock().unwrap();
+        if let Some(m) = tournaments
+            .iter_mut()
+            .find(|t| t.id == id)
+            .and_then(|t| t.rounds.get_mut(round))
+            .and_then(|r| r.matches.get_mut(index))
+        {
+            m.game = Some(game);
+            m.keys = keys;
+        }
+        self.save(&tournaments)
+    }
+
+    fn save(&self, tournaments: &[Tournament]) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(tournaments).unwrap())?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tournament(format: Format, bots: usize) -> Tournament {
+        let rules = MatchRules {
+            max_turns: 100,
+            combat: CombatRule::default(),
+            victory: Victory::default(),
+            vision: None,
+            turn_timeout: default_turn_timeout(),
+            time_bank: None,
+            disqualify: None,
+        };
+        let bots = (0..bots).map(|i| format!("bot{}", i)).collect();
+        Tournament::new(
+            "id".to_string(),
+            "test".to_string(),
+            format,
+            bots,
+            Vec::new(),
+            vec!["map".to_string()],
+            rules,
+        )
+        .unwrap()
+    }
+
+    /// Plays the current round, `winners` gives the winning player ids of every match.
+    fn play_round<F: Fn(&Match) -> Vec<u64>>(tournament: &mut Tournament, winners: F) {
+        let round = tournament.rounds.len() - 1;
+        for (i, m) in tournament.rounds[round].matches.iter_mut().enumerate() {
+            m.game = Some(format!("{}-{}", round, i));
+        }
+        let results: Vec<(String, Vec<u64>)> = tournament.rounds[round]
+            .matches
+            .iter()
+            .map(|m| (m.game.clone().unwrap(), winners(m)))
+            .collect();
+
+        tournament.update(|game| {
+            let (_, winners) = results.iter().find(|(id, _)| id == game).unwrap();
+            GameResult::Finished(winners.clone())
+        });
+    }
+
+    fn pairs(round: &Round) -> Vec<(usize, usize)> {
+        round
+            .matches
+            .iter()
+            .map(|m| (m.bots[0].min(m.bots[1]), m.bots[0].max(m.bots[1])))
+            .collect()
+    }
+
+    #[test]
+    fn only_the_token_of_a_bot_is_accepted() {
+        let mut t = tournament(Format::RoundRobin, 2);
+        assert_eq!(t.tokens.len(), 2);
+        assert_ne!(t.tokens[0], t.tokens[1]);
+        assert!(t.has_token(0, &t.tokens[0].clone()));
+        assert!(!t.has_token(0, &t.tokens[1].clone()));
+        assert!(!t.has_token(2, &t.tokens[1].clone()));
+
+        t.rounds[0].matches[0].keys = vec![1, 2];
+        let public = serde_json::to_value(t.without_secrets()).unwrap();
+        assert!(public.get("tokens").is_none());
+        assert!(public["rounds"][0]["matches"][0].get("keys").is_none());
+
+        // Tournaments from before the tokens have none, nobody gets their keys
+        let mut old = tournament(Format::RoundRobin, 2);
+        old.tokens.clear();
+        assert!(!old.has_token(0, ""));
+    }
+
+    #[test]
+    fn round_robin_pairs_every_bot_once() {
+        let mut t = tournament(Format::RoundRobin, 5);
+        while !t.is_finished() {
+            play_round(&mut t, |_| vec![0]);
+        }
+
+        assert_eq!(t.rounds.len(), 5);
+        let mut played: Vec<(usize, usize)> = t.rounds.iter().flat_map(pairs).collect();
+        played.sort();
+        played.dedup();
+        assert_eq!(played.len(), 10);
+
+        let mut byes: Vec<usize> = t.rounds.iter().flat_map(|r| r.byes.clone()).collect();
+        byes.sort();
+        assert_eq!(byes, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn swiss_avoids_rematches() {
+        let mut t = tournament(Format::Swiss(3), 6);
+        while !t.is_finished() {
+            play_round(&mut t, |_| vec![0]);
+        }
+
+        assert_eq!(t.rounds.len(), 3);
+        let mut played: Vec<(usize, usize)> = t.rounds.iter().flat_map(pairs).collect();
+        played.sort();
+        let matches = played.len();
+        played.dedup();
+        assert_eq!(played.len(), matches);
+    }
+
+    #[test]
+    fn swiss_gives_no_bot_a_second_bye() {
+        let mut t = tournament(Format::Swiss(5), 5);
+        while !t.is_finished() {
+            play_round(&mut t, |_| vec![1]);
+        }
+
+        let mut byes: Vec<usize> = t.rounds.iter().flat_map(|r| r.byes.clone()).collect();
+        byes.sort();
+        byes.dedup();
+        assert_eq!(byes.len(), 5);
+    }
+
+    #[test]
+    fn elimination_pairs_best_seeds_with_worst() {
+        let t = tournament(Format::Elimination, 7);
+        assert_eq!(t.rounds[0].byes, vec![0]);
+        assert_eq!(pairs(&t.rounds[0]), vec![(3, 4), (1, 6), (2, 5)]);
+    }
+
+    #[test]
+    fn elimination_places_the_seeds_in_a_bracket() {
+        let t = tournament(Format::Elimination, 8);
+        let first: Vec<Vec<usize>> = t.rounds[0].matches.iter().map(|m| m.bots.clone()).collect();
+        assert_eq!(first, vec![vec![0, 7], vec![3, 4], vec![1, 6], vec![2, 5]]);
+
+        let t = tournament(Format::Elimination, 6);
+        assert_eq!(t.rounds[0].byes, vec![0, 1]);
+        assert_eq!(pairs(&t.rounds[0]), vec![(3, 4), (2, 5)]);
+    }
+
+    #[test]
+    fn elimination_top_seeds_only_meet_in_the_final() {
+        // The better seed wins, the worse seed wins, or it depends on the match
+        let results: Vec<fn(&Match) -> Vec<u64>> = vec![
+            |m| vec![if m.bots[0] < m.bots[1] { 0 } else { 1 }],
+            |m| vec![if m.bots[0] < m.bots[1] { 1 } else { 0 }],
+            |m| vec![((m.bots[0] + m.bots[1]) % 2) as u64],
+        ];
+
+        for bots in 2..=17 {
+            for result in results.iter() {
+                let mut t = tournament(Format::Elimination, bots);
+                while !t.is_finished() {
+                    play_round(&mut t, result);
+                }
+
+                let last = t.rounds.len() - 1;
+                for (i, round) in t.rounds.iter().enumerate() {
+                    let meet = pairs(round).contains(&(0, 1));
+                    assert!(!meet || i == last, "{} bots meet in round {}", bots, i + 1);
+                }
+                assert_eq!(t.rounds[last].matches.len(), 1);
+            }
+        }
+
+        // When the top seeds win, they play the final
+        let mut t = tournament(Format::Elimination, 8);
+        while !t.is_finished() {
+            play_round(&mut t, results[0]);
+        }
+        assert_eq!(pairs(t.rounds.last().unwrap()), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn rounds_with_a_single_bye_are_read() {
+        let round: Round = serde_json::from_str(r#"{"matches": [], "bye": 3}"#).unwrap();
+        assert_eq!(round.byes, vec![3]);
+        let round: Round = serde_json::from_str(r#"{"matches": [], "bye": null}"#).unwrap();
+        assert!(round.byes.is_empty());
+        let round: Round = serde_json::from_str(r#"{"matches": [], "byes": [0, 1]}"#).unwrap();
+        assert_eq!(round.byes, vec![0, 1]);
+    }
+
+    #[test]
+    fn elimination_draws_advance_the_best_seed() {
+        let mut t = tournament(Format::Elimination, 8);
+        while !t.is_finished() {
+            // Player 1 is listed first, as if the draw went its way
+            play_round(&mut t, |_| vec![1, 0]);
+        }
+
+        let standings = t.standings();
+        assert_eq!(t.rounds.len(), 3);
+        assert_eq!(t.rounds[2].matches[0].advancing(), Some(0));
+        assert_eq!(standings.len(), 8);
+    }
+
+    #[test]
+    fn elimination_gives_no_bot_a_second_bye() {
+        let mut t = tournament(Format::Elimination, 10);
+        while !t.is_finished() {
+            play_round(&mut t, |_| vec![0]);
+        }
+
+        let byes: Vec<usize> = t.rounds.iter().flat_map(|r| r.byes.clone()).collect();
+        let mut unique = byes.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(byes.len(), unique.len());
+        assert!(!byes.is_empty());
+    }
+}
@@ -1,16 +1,26 @@
+use crate::planetwars::pw_stats::PlayerStats;
 use crate::planetwars::FinishedState;
+use crate::store::{self, GameRecord, GameStatus};
 use mozaic::util::request::Connect;
 use serde_json::Value;
 
+use rocket::http::Status;
+use rocket::response::status;
+use rocket_contrib::json::Json;
+
+use serde::Serialize;
+
 use std::cmp::Ordering;
-use std::sync::{Arc, Mutex};
+use std::fmt;
 use std::time::SystemTime;
 
-static NAV: [(&'static str, &'static str); 6] = [
+static NAV: [(&'static str, &'static str); 8] = [
     ("/", "Home"),
     ("/mapbuilder", "Map Builder"),
     ("/lobby", "Lobby"),
     ("/visualizer", "Visualizer"),
+    ("/leaderboard", "Leaderboard"),
+    ("/tournaments", "Tournaments"),
     ("/debug", "Debug Station"),
     ("/info", "Info"),
 ];
@@ -62,6 +72,7 @@ fn partial_cmp(a: &SystemTime, b: &SystemTime) -> Option<Ordering> {
 /// The GameState is the state of a game.
 /// Either Finished, so the game is done, not running, and there is a posible visualization.
 /// Or Playing, the game is still being managed by the mozaic framework.
+/// Or Interrupted, the server stopped while the game was running, only a partial log remains.
 #[derive(Serialize, Educe)]
 #[serde(tag = "type")]
 #[educe(PartialEq, Eq, PartialOrd, Ord)]
@@ -73,6 +84,7 @@ pub enum GameState {
 
         name: String,
         map: String,
+        file: String,
         players: Vec<PlayerStatus>,
         connected: usize,
         total: usize,
@@ -86,10 +98,20 @@ pub enum GameState {
         name: String,
         map: String,
 
-        players: Vec<(String, bool)>,
+        /// Name, whether the player won and how often it misbehaved.
+        players: Vec<(String, bool, Option<PlayerStats>)>,
         turns: u64,
         file: String,
     },
+    #[educe(PartialOrd(rank = 3))]
+    Interrupted {
+        #[educe(PartialOrd(method = "partial_cmp"))]
+        time: SystemTime,
+        name: String,
+        map: String,
+        file: String,
+        total: usize,
+    },
 }
 
 impl From<FinishedState> for GameState {
@@ -100,7 +122,13 @@ impl From<FinishedState> for GameState {
             players: state
                 .players
                 .iter()
-                .map(|(id, name)| (name.clone(), state.winners.contains(&id)))
+                .map(|(id, name)| {
+                    (
+                        name.clone(),
+                        state.winners.contains(&id),
+                        state.stats.get(*id as usize).cloned(),
+                    )
+                })
                 .collect(),
             map: state.map,
             name: state.name,
@@ -111,6 +139,42 @@ impl From<FinishedState> for GameState {
     }
 }
 
+/// The state of a stored game that is not running anymore.
+impl From<GameRecord> for GameState {
+    fn from(game: GameRecord) -> Self {
+        match game.status {
+            GameStatus::Finished { state } => state.into(),
+            _ => GameState::Interrupted {
+                time: game.time,
+                name: game.name,
+                map: game.map,
+                file: game.file,
+                total: game.players,
+            },
+        }
+    }
+}
+
+impl GameState {
+    /// Games are identified by the name of their log file, without extension.
+    pub fn id(&self) -> &str {
+        let file = match self {
+            GameState::Playing { file, .. } => file,
+            GameState::Finished { file, .. } => file,
+            GameState::Interrupted { file, .. } => file,
+        };
+        store::file_id(file)
+    }
+
+    pub fn time(&self) -> SystemTime {
+        match self {
+            GameState::Playing { time, .. } => *time,
+            GameState::Finished { time, .. } => *time,
+            GameState::Interrupted { time, .. } => *time,
+        }
+    }
+}
+
 /// Link struct, holding all necessary information
 #[derive(Serialize)]
 struct Link {
@@ -167,26 +231,16 @@ impl Context<()> {
     }
 }
 
-/// State of current live games
-pub struct Games {
-    inner: Arc<Mutex<Vec<(String, u64, SystemTime)>>>,
-}
-
-impl Games {
-    pub fn new() -> Self {
-        Self {
-            inner: Arc::new(Mutex::new(Vec::new())),
-        }
-    }
-
-    pub fn add_game(&self, name: String, id: u64) {
-        self.inner
-            .lock()
-            .unwrap()
-            .push((name, id, SystemTime::now()));
-    }
-
-    pub fn get_games(&self) -> Vec<(String, u64, SystemTime)> {
-        self.inner.lock().unwrap().clone()
-    }
+/// Error response of the json routes.
+pub type JsonError = status::Custom<Json<Value>>;
+
+/// Builds a json error response, holding the error itself and a readable message.
+pub fn json_error<E: Serialize + fmt::Display>(status: Status, error: E) -> JsonError {
+    status::Custom(
+        status,
+        Json(json!({
+            "error": error,
+            "message": error.to_string(),
+        })),
+    )
 }
@@ -1,5 +1,5 @@
 const ids = {};
-["map_holder", "name", "turns", "nop", "lobby"].forEach(id => ids[id] = document.getElementById(id));
+["map_holder", "name", "turns", "nop", "ai", "turn_timeout", "time_bank", "victory", "domination", "lobby"].forEach(id => ids[id] = document.getElementById(id));
 
 var last_map;
 var last_url;
@@ -26,8 +26,26 @@ async function start_game() {
         "name": ids["name"].value,
         "map": last_url,
         "max_turns": parseInt(ids["turns"].value),
+        "turn_timeout": parseInt(ids["turn_timeout"].value),
     };
 
+    // Only the first player connects, the others are played by the AI
+    if (ids["ai"].value) {
+        obj["players"] = ["key"];
+        for (let i = 1; i < obj["nop"]; i++) {
+            obj["players"].push(ids["ai"].value);
+        }
+    }
+
+    if (ids["time_bank"].value) {
+        obj["time_bank"] = parseInt(ids["time_bank"].value);
+    }
+
+    obj["victory"] = { "type": ids["victory"].value };
+    if (ids["victory"].value == "domination") {
+        obj["victory"]["value"] = parseFloat(ids["domination"].value) / 100;
+    }
+
     const xhr = new XMLHttpRequest();
 
     xhr.onreadystatechange = async function() {
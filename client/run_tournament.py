@@ -0,0 +1,30 @@
+#!/usr/bin/env python3
+# Plays all matches of one bot in a tournament.
+# Usage: ./run_tournament.py <tournament id> <bot name> <token> <command to run the bot...>
+# WEB_HOST is the web server (host:port), HOST the game server the bots connect to.
+
+import requests, subprocess, os, sys, time
+
+web_host = os.getenv("WEB_HOST") or "localhost:8000"
+host = os.getenv("HOST") or "localhost"
+runner = os.path.join(os.path.dirname(os.path.abspath(__file__)), "runner.py")
+
+if len(sys.argv) < 5:
+    sys.exit(f"Usage: {sys.argv[0]} <tournament id> <bot name> <token> <command to run the bot...>")
+
+tournament, bot, token, command = sys.argv[1], sys.argv[2], sys.argv[3], sys.argv[4:]
+played = set()
+
+while True:
+    url = f"http://{web_host}/api/v1/tournaments/{tournament}"
+    if requests.get(url).json()["finished"]:
+        break
+
+    for match in requests.get(f"{url}/bots/{bot}", params={"token": token}).json():
+        if match["game"] not in played:
+            played.add(match["game"])
+            print(f"Playing {match['opponent']} on {match['map']}")
+            subprocess.run([sys.executable, runner, "-n", bot, "--host", host, "-p", "9142",
+                            "-i", str(match["key"])] + command)
+
+    time.sleep(2)
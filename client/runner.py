@@ -2,6 +2,9 @@
 
 import socket, sys, subprocess, argparse, io, threading, json
 
+# The protocol version this runner speaks, sent to the server in the welcome answer
+PROTOCOL_VERSION = 3
+
 
 def execute(cmd):
     popen = subprocess.Popen(cmd, stdin=subprocess.PIPE, stdout=subprocess.PIPE, universal_newlines=True)
@@ -34,6 +37,8 @@ def main():
                         help='What port to connect to')
     parser.add_argument('--name', '-n', required=True,
                         help='Who are you?')
+    parser.add_argument('--welcome', action='store_true',
+                        help='Pass the welcome message to the bot, which answers it')
     parser.add_argument('arguments', nargs=argparse.REMAINDER,
                         help='How to run the bot')
     args = parser.parse_args()
@@ -51,6 +56,12 @@ def main():
     while line:
         print(line)
         content = json.loads(line)
+        if content["type"] == "welcome":
+            if args.welcome:
+                stdin.write(json.dumps(content["content"])+"\n")
+                stdin.flush()
+            else:
+                sock.sendall(f"{json.dumps({'protocol_version': PROTOCOL_VERSION})}\n".encode("utf8"))
         if content["type"] == "game_state":
             stdin.write(json.dumps(content["content"])+"\n")
             stdin.flush()
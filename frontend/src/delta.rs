@@ -0,0 +1,44 @@
+//! Reads compact game logs, the first line holds the planets
+//! and every following line what changed in a turn.
+//! The turns are decoded by the protocol crate, just like the server expands them.
+
+use crate::types::{Action, Planet, State};
+use planetwars_protocol::delta::{self, TurnDelta};
+
+#[derive(Debug, Clone, Deserialize)]
+struct Header {
+    format: String,
+    planets: Vec<Planet>,
+}
+
+/// Keeps the current state, to apply the changes of every turn to
+pub struct Decoder {
+    decoder: delta::Decoder,
+}
+
+impl Decoder {
+    /// The header line of a compact log starts a decoder, other lines don't
+    pub fn from_header(line: &str) -> Option<Self> {
+        let header: Header = serde_json::from_str(line).ok()?;
+        if header.format != delta::FORMAT {
+            return None;
+        }
+
+        Some(Self {
+            decoder: delta::Decoder::new(&header.planets),
+        })
+    }
+
+    pub fn decode(&mut self, line: &str) -> Option<State> {
+        let delta: TurnDelta<Action> = serde_json::from_str(line).ok()?;
+        let turn = self.decoder.decode(delta).ok()?;
+
+        Some(State {
+            planets: turn.state.planets,
+            expeditions: turn.state.expeditions,
+            visible: turn.visible,
+            actions: turn.actions,
+            messages: Vec::new(),
+        })
+    }
+}
@@ -1,17 +1,22 @@
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate flate2;
 extern crate octoon_math;
+extern crate planetwars_protocol;
 extern crate serde_json;
 extern crate voronoi;
 
 use octoon_math::Mat3;
 use voronoi::{make_polygons, voronoi, Point};
 
+mod delta;
 mod types;
 mod utils;
 
+use flate2::read::GzDecoder;
 use std::collections::HashMap;
+use std::io::Read;
 use wasm_bindgen::prelude::*;
 
 macro_rules! console_log {
@@ -44,10 +49,10 @@ fn spr(from: f32) -> f32 {
 
 impl Circle {
     pub fn new(p1: &types::Planet, p2: &types::Planet) -> Self {
-        let x1 = p1.x;
-        let y1 = p1.y;
-        let x2 = p2.x;
-        let y2 = p2.y;
+        let x1 = p1.x as f32;
+        let y1 = p1.y as f32;
+        let x2 = p2.x as f32;
+        let y2 = p2.y as f32;
 
         // Distance between planets
         let q = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
@@ -113,12 +118,12 @@ impl Circle {
 }
 
 fn create_voronoi(planets: &Vec<types::Planet>, bbox: f32) -> (Vec<f32>, Vec<usize>) {
-    let mut verts: Vec<[f32; 2]> = planets.iter().map(|p| [p.x, p.y]).collect();
+    let mut verts: Vec<[f32; 2]> = planets.iter().map(|p| [p.x as f32, p.y as f32]).collect();
     let mut ids = Vec::new();
 
     let vor_points = planets
         .iter()
-        .map(|p| Point::new(p.x as f64, p.y as f64))
+        .map(|p| Point::new(p.x, p.y))
         .collect();
 
     let vor = voronoi(vor_points, bbox as f64);
@@ -143,10 +148,24 @@ fn create_voronoi(planets: &Vec<types::Planet>, bbox: f32) -> (Vec<f32>, Vec<usi
     (verts.concat(), ids)
 }
 
+/// Parses a line of a full log, or of a compact one when there is a decoder
+fn parse_state(delta: &mut Option<delta::Decoder>, line: &str) -> Option<types::State> {
+    match delta {
+        Some(decoder) => decoder.decode(line),
+        None => serde_json::from_str(line).ok(),
+    }
+}
+
 #[wasm_bindgen]
 pub struct Game {
     states: Vec<types::State>,
+    // Set when the states come from a compact log
+    delta: Option<delta::Decoder>,
+    // Most expeditions in one state, this many ships are drawn
+    max_ships: usize,
     turn: usize,
+    // Player whose view is shown, 0 shows everything
+    view: usize,
 
     planet_map: HashMap<(String, String), Circle>,
 
@@ -175,24 +194,30 @@ impl Game {
 
         console_log!("Rust is busy being awesome!");
 
+        let mut lines = file.split("\n").filter(|line| !line.trim().is_empty()).peekable();
+        let mut delta = lines.peek().and_then(|line| delta::Decoder::from_header(line));
+        if delta.is_some() {
+            lines.next();
+        }
+
         // First line is fucked but we just filter out things that cannot parse
-        let states: Vec<types::State> = file
-            .split("\n")
-            .filter_map(|line| serde_json::from_str(line).ok())
+        let states: Vec<types::State> = lines
+            .filter_map(|line| parse_state(&mut delta, line))
             .collect();
+        let first = states.first().expect("The file holds no states");
 
         let mut planet_map = HashMap::new();
 
         // Iterator?
-        for p1 in states[0].planets.iter() {
-            for p2 in states[0].planets.iter() {
+        for p1 in first.planets.iter() {
+            for p2 in first.planets.iter() {
                 planet_map.insert((p1.name.clone(), p2.name.clone()), Circle::new(&p1, &p2));
             }
         }
-        let view_box = utils::caclulate_viewbox(&states[0].planets);
+        let view_box = utils::caclulate_viewbox(&first.planets);
 
         let (voronoi_vertices, voronoi_indices) =
-            create_voronoi(&states[0].planets, view_box[2].max(view_box[3]));
+            create_voronoi(&first.planets, view_box[2].max(view_box[3]));
 
         let voronoi_colors: Vec<f32> = voronoi_indices
             .iter()
@@ -200,14 +225,17 @@ impl Game {
             .collect::<Vec<[f32; 3]>>()
             .concat(); // Init these colours on black
 
-        Self {
-            planets: utils::get_planets(&states[0].planets, 2.0),
+        let mut game = Self {
+            planets: utils::get_planets(&first.planets, 1.0),
             planet_ships: Vec::new(),
             view_box,
 
             planet_map,
             turn: 0,
-            states,
+            view: 0,
+            states: Vec::new(),
+            delta,
+            max_ships: 0,
             ship_locations: Vec::new(),
             ship_label_locations: Vec::new(),
             ship_colours: Vec::new(),
@@ -217,9 +245,49 @@ impl Game {
             voronoi_vertices,
             voronoi_indices,
             voronoi_colors,
+        };
+
+        for state in states {
+            game.add_state(state);
+        }
+        game
+    }
+
+    /// A game from a gzip compressed log
+    pub fn from_gzip(data: &[u8]) -> Self {
+        let mut file = String::new();
+        GzDecoder::new(data)
+            .read_to_string(&mut file)
+            .expect("The file is not a gzip compressed log");
+
+        Self::new(&file)
+    }
+
+    /// Appends the state of a newly played turn, returns whether the line was a state
+    pub fn push_state(&mut self, line: &str) -> bool {
+        match parse_state(&mut self.delta, line) {
+            Some(state) => {
+                self.add_state(state);
+                true
+            }
+            None => false,
         }
     }
 
+    /// Appends the states of newly played turns, one per line, returns the new turn count
+    pub fn push_states(&mut self, lines: &str) -> usize {
+        for line in lines.split("\n") {
+            self.push_state(line);
+        }
+
+        self.states.len()
+    }
+
+    fn add_state(&mut self, state: types::State) {
+        self.max_ships = self.max_ships.max(state.expeditions.len());
+        self.states.push(state);
+    }
+
     pub fn get_viewbox(&self) -> Vec<f32> {
         self.view_box.clone()
     }
@@ -232,6 +300,47 @@ impl Game {
         self.planet_ships.clone()
     }
 
+    /// 1 for every planet the current view can see, 0 for the others
+    pub fn get_planet_visibility(&self) -> Vec<u8> {
+        self.states[self.turn]
+            .planets
+            .iter()
+            .map(|p| self.sees_planet(self.turn, p) as u8)
+            .collect()
+    }
+
+    /// Show the game as the given player saw it, 0 shows everything
+    pub fn set_view(&mut self, player: usize) -> usize {
+        self.view = player;
+        self.update_turn(self.turn)
+    }
+
+    /// The debug notes bots sent with this turn, one line per bot
+    pub fn get_debug(&self) -> String {
+        self.shown_debug()
+            .filter(|(_, debug)| !debug.text.is_empty())
+            .map(|(player, debug)| format!("Player {}: {}", player, debug.text))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// 1 for every planet a bot highlighted this turn, 0 for the others
+    pub fn get_planet_highlights(&self) -> Vec<u8> {
+        self.states[self.turn]
+            .planets
+            .iter()
+            .map(|p| {
+                self.shown_debug()
+                    .any(|(_, debug)| debug.planets.contains(&p.name)) as u8
+            })
+            .collect()
+    }
+
+    /// The messages the player received this turn, one per line
+    pub fn get_messages(&self) -> String {
+        self.states[self.turn].messages.join("\n")
+    }
+
     pub fn get_planet_colors(&self) -> Vec<f32> {
         self.current_planet_colours.clone()
     }
@@ -260,9 +369,34 @@ impl Game {
             .collect();
     }
 
+    /// Players only see what was in their vision, when the game had fog of war
+    fn sees_planet(&self, turn: usize, planet: &types::Planet) -> bool {
+        self.view == 0
+            || self.states[turn]
+                .visible
+                .get(self.view - 1)
+                .map_or(true, |v| v.planets.contains(&planet.name))
+    }
+
+    fn sees_expedition(&self, turn: usize, expedition: &types::Expedition) -> bool {
+        self.view == 0
+            || self.states[turn]
+                .visible
+                .get(self.view - 1)
+                .map_or(true, |v| v.expeditions.contains(&expedition.id))
+    }
+
+    fn planet_color(&self, turn: usize, planet: &types::Planet) -> [f32; 3] {
+        if self.sees_planet(turn, planet) {
+            utils::COLORS[planet.owner.unwrap_or(0) % utils::COLORS.len()]
+        } else {
+            utils::FOG_COLOR
+        }
+    }
+
     fn update_voronoi_colors(&mut self) {
         for (i, p) in self.states[self.turn].planets.iter().enumerate() {
-            let color = utils::COLORS[p.owner.unwrap_or(0) as usize % utils::COLORS.len()];
+            let color = self.planet_color(self.turn, p);
             self.voronoi_colors[i * 3 + 0] = color[0];
             self.voronoi_colors[i * 3 + 1] = color[1];
             self.voronoi_colors[i * 3 + 2] = color[2];
@@ -271,16 +405,13 @@ impl Game {
 
     fn update_planet_colours(&mut self) {
         let mut new_vec: Vec<[f32; 3]> = Vec::new();
+        let later = (self.turn + 1).min(self.states.len() - 1);
         let planets_now = self.states[self.turn].planets.iter();
-        let planets_later = self.states[(self.turn + 1).min(self.states.len() - 1)]
-            .planets
-            .iter();
+        let planets_later = self.states[later].planets.iter();
 
         for (p1, p2) in planets_now.zip(planets_later) {
-            new_vec
-                .push(utils::COLORS[p1.owner.unwrap_or(0) as usize % utils::COLORS.len()].into());
-            new_vec
-                .push(utils::COLORS[p2.owner.unwrap_or(0) as usize % utils::COLORS.len()].into());
+            new_vec.push(self.planet_color(self.turn, p1));
+            new_vec.push(self.planet_color(later, p2));
         }
 
         self.current_planet_colours = new_vec.concat::<f32>();
@@ -292,10 +423,15 @@ impl Game {
 
         let t = Mat3::new(0.2, 0., 0., 0., 0.2, 0.0, 0., -0.5, 0.2);
 
-        for ship in self.states[self.turn].expeditions.iter() {
+        for ship in self.visible_expeditions() {
+            // Logs hold full states, hidden planets only happen in the messages to players
+            let route = match (&ship.origin, &ship.destination) {
+                (Some(origin), Some(destination)) => (origin.clone(), destination.clone()),
+                _ => continue,
+            };
             let ((o1, a1), (o2, a2)) = self
                 .planet_map
-                .get(&(ship.origin.clone(), ship.destination.clone()))
+                .get(&route)
                 .unwrap()
                 .get_for_remaining(ship.turns_remaining as usize);
             new_sl.push((o1 * Mat3::rotate_z(a1)).to_array());
@@ -308,28 +444,42 @@ impl Game {
         self.ship_locations = new_sl.concat();
         self.ship_label_locations = new_sll.concat();
 
-        self.ship_colours = self.states[self.turn]
-            .expeditions
+        self.ship_colours = self
+            .visible_expeditions()
             .iter()
-            .map(|s| utils::COLORS[s.owner as usize % utils::COLORS.len()])
+            .map(|s| utils::COLORS[s.owner % utils::COLORS.len()])
             .collect::<Vec<[f32; 3]>>()
             .concat();
     }
 
     fn update_ship_counts(&mut self) {
-        self.ship_counts = self.states[self.turn]
-            .expeditions
+        self.ship_counts = self
+            .visible_expeditions()
             .iter()
             .map(|s| s.ship_count as usize)
             .collect();
     }
 
-    pub fn get_max_ships(&self) -> usize {
-        self.states
+    /// Debug notes of the current turn, only those of the viewing player when there is one
+    fn shown_debug(&self) -> impl Iterator<Item = (u64, &types::Debug)> {
+        let view = self.view as u64;
+        self.states[self.turn]
+            .actions
             .iter()
-            .map(|s| s.expeditions.len())
-            .max()
-            .unwrap()
+            .filter(move |a| view == 0 || a.player == view)
+            .filter_map(|a| a.debug.as_ref().map(|debug| (a.player, debug)))
+    }
+
+    fn visible_expeditions(&self) -> Vec<&types::Expedition> {
+        self.states[self.turn]
+            .expeditions
+            .iter()
+            .filter(|e| self.sees_expedition(self.turn, e))
+            .collect()
+    }
+
+    pub fn get_max_ships(&self) -> usize {
+        self.max_ships
     }
 
     pub fn get_ship_locations(&self) -> Vec<f32> {
@@ -25,25 +25,24 @@ pub static COLORS: [[f32; 3]; 10] = [
     [0.05, 0.77, 1.0],  // #0DC5FF
 ];
 
+/// Colour of planets hidden by the fog of war
+pub static FOG_COLOR: [f32; 3] = [0.15, 0.15, 0.15];
+
 use super::types;
 
 pub fn caclulate_viewbox(planets: &Vec<types::Planet>) -> Vec<f32> {
     let mut iter = planets.iter();
 
     let init = match iter.next() {
-        Some(p) => (p.x, p.y, p.x, p.y),
+        Some(p) => (p.x as f32, p.y as f32, p.x as f32, p.y as f32),
         None => return vec![0.0, 0.0, 0.0, 0.0],
     };
     let (min_x, min_y, max_x, max_y) =
         planets
             .iter()
             .fold(init, |(min_x, min_y, max_x, max_y), p| {
-                (
-                    min_x.min(p.x),
-                    min_y.min(p.y),
-                    max_x.max(p.x),
-                    max_y.max(p.y),
-                )
+                let (x, y) = (p.x as f32, p.y as f32);
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
             });
 
     let (width, height) = (max_x - min_x, max_y - min_y);
@@ -55,11 +54,12 @@ pub fn caclulate_viewbox(planets: &Vec<types::Planet>) -> Vec<f32> {
     vec![min_x - dx / 2.0, min_y - dy / 2.0, width + dx, height + dy]
 }
 
+/// Flattens planets to [x, y, radius], planets without a radius get the given default radius
 pub fn get_planets(planets: &Vec<types::Planet>, r: f32) -> Vec<f32> {
     planets.iter().fold(Vec::new(), |mut cum, p| {
-        cum.push(p.x);
-        cum.push(p.y);
-        cum.push(r);
+        cum.push(p.x as f32);
+        cum.push(p.y as f32);
+        cum.push(p.radius.map_or(r, |r| r as f32));
         cum
     })
 }
@@ -1,11 +1,17 @@
 
-import { set_game_name, set_loading, set_instance } from './index'
+import { set_game_name, set_loading, set_instance, push_states, set_view } from './index'
 
 var game_name, game_file;
+var live_source: EventSource;
+// Player whose messages are shown, 0 shows the game as spectators saw it
+var perspective = 0;
 
 document.getElementById("addbutton").onclick = function () {
     const loc = window.location;
-    const query = `?game=${game_file}&name=${game_name}`;
+    let query = `?game=${game_file}&name=${game_name}`;
+    if (perspective) {
+        query += `&player=${perspective}`;
+    }
     navigator.clipboard.writeText(loc.origin + loc.pathname + encodeURI(query)).then(() => {
         console.log("Success");
     }, () => {
@@ -17,7 +23,14 @@ async function on_load() {
     const options = document.getElementsByClassName("options");
     const urlVars = new URLSearchParams(window.location.search);
 
-    if (urlVars.get("game") && urlVars.get("name")) {
+    if (urlVars.get("live") && urlVars.get("name")) {
+        handle_live(urlVars.get("live"), urlVars.get("name"))
+    } else if (urlVars.get("game") && urlVars.get("name")) {
+        if (urlVars.get("player")) {
+            const input = <HTMLInputElement>document.getElementById("perspective");
+            input.value = urlVars.get("player");
+            perspective = Math.max(0, parseInt(input.value) || 0);
+        }
         console.log(urlVars.get("game") + ' ' + urlVars.get("name"))
         handle(urlVars.get("game"), urlVars.get("name"))
     } else if (options[0]) {
@@ -30,18 +43,138 @@ async function on_load() {
 
 window.addEventListener("load", on_load, false);
 
+document.getElementById("perspective").onchange = function () {
+    perspective = Math.max(0, parseInt((<HTMLInputElement>this).value) || 0);
+    if (game_file) {
+        handle(game_file, game_name);
+    }
+}
+
 export function handle(location: string, name: string) {
     game_file = location;
     game_name = name;
 
+    stop_live();
+    set_loading(true);
+
+    // The server replays the game to show it as the player saw it
+    const url = perspective ? `${location}?player=${perspective}` : location;
+    load_progressively(url, name).catch(console.error);
+}
+
+// Counts the games that started loading, so an older one stops when another is picked
+var loads = 0;
+
+// Hands the log to the visualizer as it comes in, a chunk at a time,
+// so the first turns show while the rest is still loading and big logs don't freeze the page
+async function load_progressively(location: string, name: string) {
+    const load = ++loads;
+    const response = await fetch(location);
+    if (!response.ok) {
+        throw new Error(await response.text());
+    }
+    const reader = response.body.getReader();
+    const decoder = new TextDecoder();
+
+    let started = false;
+    let pending = "";
+    let gzip: Uint8Array[];
+    while (true) {
+        const { done, value } = await reader.read();
+        if (load != loads) {
+            reader.cancel();
+            return;
+        }
+
+        // Gzip compressed logs can only be shown once they are complete
+        if (!started && !gzip && value && value[0] == 0x1f && value[1] == 0x8b) {
+            gzip = [];
+        }
+        if (gzip) {
+            if (value) {
+                gzip.push(value);
+            }
+            if (done) {
+                await set_instance(concat(gzip));
+                set_game_name(name);
+                set_view(perspective ? 1 : 0);
+                return;
+            }
+            continue;
+        }
+
+        pending += done ? decoder.decode() : decoder.decode(value, { stream: true });
+        // Only complete lines, the rest comes with the next chunk
+        const end = done ? pending.length : pending.lastIndexOf("\n") + 1;
+        const lines = pending.slice(0, end);
+
+        if (!started) {
+            // The first line is the header of the log, wait for a state
+            if (lines.split("\n").filter((line) => line.trim()).length >= 2) {
+                pending = pending.slice(end);
+                await set_instance(lines);
+                set_game_name(name);
+                // The game is rotated, so the player is always player 1
+                set_view(perspective ? 1 : 0);
+                started = true;
+            } else if (done) {
+                throw new Error(`${location} holds no states`);
+            }
+        } else {
+            pending = pending.slice(end);
+            push_states(lines);
+        }
+
+        if (done) {
+            return;
+        }
+
+        // Let the page render between chunks
+        await new Promise((resolve) => setTimeout(resolve, 0));
+    }
+}
+
+function concat(chunks: Uint8Array[]): Uint8Array {
+    const all = new Uint8Array(chunks.reduce((length, chunk) => length + chunk.length, 0));
+    let offset = 0;
+    for (const chunk of chunks) {
+        all.set(chunk, offset);
+        offset += chunk.length;
+    }
+    return all;
+}
+
+// Follows a running game, starting with every turn that was already played
+export function handle_live(id: string, name: string) {
+    game_name = name;
+
+    // Stop a log that is still loading
+    loads++;
+    stop_live();
     set_loading(true);
 
-    fetch(location)
-        .then((r) => r.text())
-        .then((response) => {
-            set_instance(response);
-            set_game_name(name);
-        }).catch(console.error);
+    let loaded: Promise<void>;
+    live_source = new EventSource(`/api/v1/games/${id}/spectate`);
+    live_source.onmessage = (event) => {
+        if (loaded) {
+            loaded = loaded.then(() => push_states(event.data));
+        } else {
+            loaded = set_instance(event.data).then(() => set_game_name(name));
+        }
+        loaded.catch(console.error);
+    };
+    live_source.addEventListener("finished", stop_live);
+    live_source.onerror = (event) => {
+        console.error(event);
+        stop_live();
+    };
+}
+
+function stop_live() {
+    if (live_source) {
+        live_source.close();
+        live_source = undefined;
+    }
 }
 
 on_load();
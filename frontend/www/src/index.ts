@@ -65,6 +65,9 @@ const ELEMENTS = {};
   "turnSlider",
   "fileselect",
   "speed",
+  "view",
+  "messages",
+  "debug",
   "canvas",
 ].forEach((n) => (ELEMENTS[n] = document.getElementById(n)));
 
@@ -105,12 +108,15 @@ class GameInstance {
 
   renderer: Renderer;
   planet_count: number;
+  ship_mesh: Mesh;
 
   vor_builder: VoronoiBuilder;
 
   vor_counter = 3;
   use_vor = true;
   playing = true;
+  // Stopped because there were no more turns
+  at_end = false;
   time_stopped_delta = 0;
   last_time = 0;
   frame = -1;
@@ -149,7 +155,8 @@ class GameInstance {
     // List of [(x, y, r)] for all planets
     this._create_voronoi(planets);
     this._create_planets(planets, meshes);
-    this._create_shipes(ship_mesh);
+    this.ship_mesh = ship_mesh;
+    this._create_shipes();
 
     // Set slider correctly
     this.turn_count = game.turn_count();
@@ -175,13 +182,16 @@ class GameInstance {
 
   _create_planets(planets: Float32Array, meshes: Mesh[]) {
     for (let i = 0; i < this.planet_count; i++) {
+      // Planet meshes have radius 1
+      const scale = planets[i * 3 + 2];
+
       {
         const transform = new UniformMatrix3fv([
-          1,
+          scale,
           0,
           0,
           0,
-          1,
+          scale,
           0,
           -planets[i * 3],
           -planets[i * 3 + 1],
@@ -224,7 +234,7 @@ class GameInstance {
           1,
           0,
           -planets[i * 3],
-          -planets[i * 3 + 1] - 1.2,
+          -planets[i * 3 + 1] - 1.2 * scale,
           1,
         ]);
 
@@ -235,15 +245,16 @@ class GameInstance {
     }
   }
 
-  _create_shipes(ship_mesh: Mesh) {
-    const ship_ibo = new IndexBuffer(GL, ship_mesh.cells);
-    const ship_positions = new VertexBuffer(GL, ship_mesh.positions);
+  // Creates the ships that are not drawn yet, new turns can have more of them
+  _create_shipes() {
+    const ship_ibo = new IndexBuffer(GL, this.ship_mesh.cells);
+    const ship_positions = new VertexBuffer(GL, this.ship_mesh.positions);
     const ship_layout = new VertexBufferLayout();
     ship_layout.push(GL.FLOAT, 3, 4, "a_position");
     const ship_vao = new VertexArray();
     ship_vao.addBuffer(ship_positions, ship_layout);
 
-    for (let i = 0; i < this.game.get_max_ships(); i++) {
+    for (let i = this.ship_labels.length; i < this.game.get_max_ships(); i++) {
       this.renderer.addToDraw(
         ship_ibo,
         ship_vao,
@@ -268,11 +279,31 @@ class GameInstance {
   _update_state() {
     this._update_planets();
     this._update_ships();
+    this._update_messages();
+    this._update_debug();
+  }
+
+  _update_debug() {
+    const debug = this.game.get_debug();
+    ELEMENTS["debug"].textContent = debug;
+    ELEMENTS["debug"].hidden = !debug;
+  }
+
+  _update_messages() {
+    const messages = this.game.get_messages();
+    ELEMENTS["messages"].textContent = messages
+      .split("\n")
+      .filter((message) => message)
+      .map((message) => JSON.stringify(JSON.parse(message), null, 2))
+      .join("\n");
+    ELEMENTS["messages"].hidden = !messages;
   }
 
   _update_planets() {
     const colours = this.game.get_planet_colors();
     const planet_ships = this.game.get_planet_ships();
+    const planet_visibility = this.game.get_planet_visibility();
+    const planet_highlights = this.game.get_planet_highlights();
 
     this.vor_shader.uniform(GL, "u_planet_colours", new Uniform3fv(colours));
 
@@ -300,7 +331,8 @@ class GameInstance {
 
       this.planet_labels[i].setText(
         GL,
-        "*" + planet_ships[i],
+        (planet_highlights[i] ? "!" : "") +
+          (planet_visibility[i] ? "*" + planet_ships[i] : "?"),
         Align.Middle,
         Align.Begin
       );
@@ -464,15 +496,37 @@ class GameInstance {
     COUNTER.frame_end();
   }
 
+  push_states(lines: string) {
+    const at_end = this.at_end;
+    this.turn_count = this.game.push_states(lines);
+    this._create_shipes();
+    ELEMENTS["turnSlider"].max = this.turn_count - 1 + "";
+
+    if (at_end) {
+      // Keep playing a live game
+      this.updateTurn(this.frame + 1);
+    } else {
+      ELEMENTS["turnCounter"].innerHTML =
+        this.frame + " / " + (this.turn_count - 1);
+    }
+  }
+
+  setView(player: number) {
+    this.game.set_view(player);
+    this._update_state();
+  }
+
   updateTurn(turn: number) {
     this.frame = Math.max(0, turn);
     const new_frame = this.game.update_turn(this.frame);
     if (new_frame < this.frame) {
       this.frame = new_frame;
       this.playing = false;
+      this.at_end = true;
     } else {
       this._update_state();
       this.playing = true;
+      this.at_end = false;
     }
 
     ELEMENTS["turnCounter"].innerHTML =
@@ -519,7 +573,8 @@ var game_instance: GameInstance;
 var meshes: Mesh[];
 var shaders: Dictionary<ShaderFactory>;
 
-export async function set_instance(source: string) {
+// The source is the text of a log, or a gzip compressed log
+export async function set_instance(source: string | Uint8Array) {
   if (!meshes || !shaders) {
     const mesh_promises = ["ship.svg", "earth.svg", "mars.svg", "venus.svg"]
       .map((name) => "static/res/assets/" + name)
@@ -564,7 +619,7 @@ export async function set_instance(source: string) {
   resizeCanvasToDisplaySize(CANVAS);
 
   game_instance = new GameInstance(
-    Game.new(source),
+    typeof source === "string" ? Game.new(source) : Game.from_gzip(source),
     meshes.slice(1),
     meshes[0],
     shaders
@@ -573,6 +628,12 @@ export async function set_instance(source: string) {
   set_loading(false);
 }
 
+export function push_states(lines: string) {
+  if (game_instance) {
+    game_instance.push_states(lines);
+  }
+}
+
 window.addEventListener(
   "resize",
   function () {
@@ -595,6 +656,17 @@ ELEMENTS["speed"].onchange = function () {
   ms_per_frame = parseInt(ELEMENTS["speed"].value);
 };
 
+export function set_view(player: number) {
+  ELEMENTS["view"].value = player + "";
+  ELEMENTS["view"].onchange(undefined);
+}
+
+ELEMENTS["view"].onchange = function () {
+  if (game_instance) {
+    game_instance.setView(Math.max(0, parseInt(ELEMENTS["view"].value) || 0));
+  }
+};
+
 function step(time: number) {
   if (game_instance) {
     game_instance.render(time);
@@ -0,0 +1,208 @@
+//! The turns of compact game logs, shared by the server that writes them
+//! and the visualizer that reads them.
+//!
+//! The first line of a compact log holds the planets, every following line a `TurnDelta`:
+//! planets with a new owner or ship count, new expeditions and the expeditions that arrived.
+//! Expeditions that just got a turn closer to their destination are left out.
+//! Planets are referred to by their index in the header, instead of their name.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use super::{Expedition, Planet, State, Visibility};
+
+/// Tells compact logs apart from full ones.
+pub const FORMAT: &str = "delta";
+
+/// An expedition with the indices of its planets:
+/// id, origin, destination, owner, ship count and turns remaining.
+pub type DeltaExpedition = (u64, usize, usize, usize, u64, u64);
+
+/// Every line after the header: how a turn changed the state.
+/// The actions are kept as the log has them, readers pick what they need.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnDelta<A> {
+    /// Index, owner and ship count of the planets that changed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub planets: Vec<(usize, Option<usize>, u64)>,
+    /// Dispatched expeditions, and the ones that changed in another way than getting a turn closer.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expeditions: Vec<DeltaExpedition>,
+    /// Ids of the expeditions that are gone.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arrived: Vec<u64>,
+    /// Ids of all expeditions in order, only when moving them like the rules do gives another order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<Vec<u64>>,
+    /// Read with `Vec::new`, so the actions need no `Default`.
+    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<A>,
+    /// With fog of war, the planet indices and expedition ids every player could see.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub visible: Vec<(Vec<usize>, Vec<u64>)>,
+}
+
+/// A turn of a compact log with its planets referred to by name again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedTurn<A> {
+    pub state: State,
+    pub actions: Vec<A>,
+    pub visible: Vec<Visibility>,
+}
+
+/// A turn that does not fit the turns before it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeltaError {
+    UnknownPlanet(usize),
+    UnknownExpedition(u64),
+}
+
+impl fmt::Display for DeltaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeltaError::UnknownPlanet(i) => write!(f, "unknown planet {}", i),
+            DeltaError::UnknownExpedition(id) => write!(f, "unknown expedition {}", id),
+        }
+    }
+}
+
+impl Error for DeltaError {}
+
+/// Keeps the current state, to apply the changes of every turn to.
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    state: State,
+}
+
+impl Decoder {
+    /// Starts from the planets in the header, without expeditions.
+    pub fn new(planets: &[Planet]) -> Self {
+        Decoder {
+            state: State {
+                planets: planets.to_vec(),
+                expeditions: Vec::new(),
+                time: None,
+            },
+        }
+    }
+
+    pub fn decode<A>(&mut self, delta: TurnDelta<A>) -> Result<DecodedTurn<A>, DeltaError> {
+        for (i, owner, ship_count) in delta.planets {
+            let planet = self
+                .state
+                .planets
+                .get_mut(i)
+                .ok_or(DeltaError::UnknownPlanet(i))?;
+            planet.owner = owner;
+            planet.ship_count = ship_count;
+        }
+
+        let old_ids: HashSet<u64> = self.state.expeditions.iter().map(|e| e.id).collect();
+        let mut dispatched = Vec::new();
+        let mut changed = Vec::new();
+        for exp in delta.expeditions {
+            let exp = self.expand_expedition(exp)?;
+            if old_ids.contains(&exp.id) {
+                changed.push(exp);
+            } else {
+                dispatched.push(exp);
+            }
+        }
+
+        let mut expeditions = advance(&self.state.expeditions, dispatched, &delta.arrived);
+        for exp in changed {
+            upsert(&mut expeditions, exp);
+        }
+
+        if let Some(order) = delta.order {
+            let mut by_id: HashMap<u64, Expedition> =
+                expeditions.into_iter().map(|e| (e.id, e)).collect();
+            expeditions = order
+                .iter()
+                .map(|id| by_id.remove(id).ok_or(DeltaError::UnknownExpedition(*id)))
+                .collect::<Result<_, _>>()?;
+        }
+        self.state.expeditions = expeditions;
+
+        let visible = delta
+            .visible
+            .into_iter()
+            .map(|(planets, expeditions)| {
+                Ok(Visibility {
+                    planets: planets
+                        .iter()
+                        .map(|&i| self.name(i))
+                        .collect::<Result<_, _>>()?,
+                    expeditions,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(DecodedTurn {
+            state: self.state.clone(),
+            actions: delta.actions,
+            visible,
+        })
+    }
+
+    fn expand_expedition(
+        &self,
+        (id, origin, destination, owner, ship_count, turns_remaining): DeltaExpedition,
+    ) -> Result<Expedition, DeltaError> {
+        Ok(Expedition {
+            id,
+            origin: Some(self.name(origin)?),
+            destination: Some(self.name(destination)?),
+            owner,
+            ship_count,
+            turns_remaining,
+        })
+    }
+
+    fn name(&self, i: usize) -> Result<String, DeltaError> {
+        self.state
+            .planets
+            .get(i)
+            .map(|p| p.name.clone())
+            .ok_or(DeltaError::UnknownPlanet(i))
+    }
+}
+
+/// The expeditions after a turn, moved the way the rules move them:
+/// the dispatched expeditions are added at the end, then every expedition gets a turn closer
+/// and the ones that arrived are swap removed.
+pub fn advance(
+    expeditions: &[Expedition],
+    dispatched: Vec<Expedition>,
+    arrived: &[u64],
+) -> Vec<Expedition> {
+    let mut expeditions: Vec<Expedition> = expeditions
+        .iter()
+        .cloned()
+        .chain(dispatched.into_iter().map(|e| Expedition {
+            turns_remaining: e.turns_remaining + 1,
+            ..e
+        }))
+        .collect();
+
+    let mut i = 0;
+    while i < expeditions.len() {
+        if arrived.contains(&expeditions[i].id) {
+            expeditions.swap_remove(i);
+        } else {
+            expeditions[i].turns_remaining = expeditions[i].turns_remaining.saturating_sub(1);
+            i += 1;
+        }
+    }
+
+    expeditions
+}
+
+/// Replaces the expedition with the same id, or adds it at the end.
+fn upsert(expeditions: &mut Vec<Expedition>, exp: Expedition) {
+    match expeditions.iter_mut().find(|e| e.id == exp.id) {
+        Some(old) => *old = exp,
+        None => expeditions.push(exp),
+    }
+}
@@ -0,0 +1,286 @@
+//! The json messages between the planetwars server and its bots,
+//! also used for the states in game logs.
+//!
+//! Player numbers in these messages start at 1,
+//! the states a bot receives are rotated so the bot is player 1.
+
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+pub mod delta;
+
+/// The version of these messages, raised with every change bots have to know about.
+pub const PROTOCOL_VERSION: u64 = 3;
+
+/// The capability of receiving `CompactState`s instead of `GameState`s,
+/// with the planets that never change only in the welcome message.
+pub const COMPACT_STATES: &str = "compact_states";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Expedition {
+    pub id: u64,
+    pub ship_count: u64,
+    /// Left out when the planet is hidden by fog of war.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// Left out when the planet is hidden by fog of war.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    pub owner: usize,
+    pub turns_remaining: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Planet {
+    pub ship_count: u64,
+    pub x: f64,
+    pub y: f64,
+    pub owner: Option<usize>,
+    pub name: String,
+    /// Ships added every turn while the planet is owned.
+    #[serde(default = "default_growth")]
+    pub growth: u64,
+    /// Only used to draw the planet, the default radius is 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+}
+
+fn default_growth() -> u64 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Action {
+    #[serde(rename = "moves")]
+    pub commands: Vec<Command>,
+    /// Notes for the one debugging the bot, they are logged but do not affect the game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug: Option<Debug>,
+}
+
+/// What a bot wants to show next to its turn in the visualizer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Debug {
+    #[serde(default)]
+    pub text: String,
+    /// Names of the planets to highlight
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub planets: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Command {
+    pub origin: PlanetRef,
+    pub destination: PlanetRef,
+    pub ship_count: u64,
+}
+
+/// A planet in a command, by its index in the planets of the welcome message or by its name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PlanetRef {
+    Index(usize),
+    Name(String),
+}
+
+impl From<String> for PlanetRef {
+    fn from(name: String) -> Self {
+        PlanetRef::Name(name)
+    }
+}
+
+impl From<&str> for PlanetRef {
+    fn from(name: &str) -> Self {
+        PlanetRef::Name(name.to_string())
+    }
+}
+
+impl From<usize> for PlanetRef {
+    fn from(index: usize) -> Self {
+        PlanetRef::Index(index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    pub planets: Vec<Planet>,
+    pub expeditions: Vec<Expedition>,
+    /// Only in the states sent to players, the time they have for their next turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<TimeLeft>,
+}
+
+/// A state without what never changes, planets are referred to by their index
+/// in the planets of the welcome message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompactState {
+    pub planets: Vec<CompactPlanet>,
+    pub expeditions: Vec<CompactExpedition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<TimeLeft>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompactPlanet {
+    /// Index in the planets of the welcome message
+    pub id: usize,
+    pub owner: Option<usize>,
+    pub ship_count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompactExpedition {
+    pub id: u64,
+    pub ship_count: u64,
+    /// Index in the planets of the welcome message, left out when hidden by fog of war
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<usize>,
+    /// Index in the planets of the welcome message, left out when hidden by fog of war
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<usize>,
+    pub owner: usize,
+    pub turns_remaining: u64,
+}
+
+/// A planet as it is for the whole game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapPlanet {
+    pub name: String,
+    pub x: f64,
+    pub y: f64,
+    pub growth: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+}
+
+/// Time a player has left, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeLeft {
+    /// Time for this turn, without touching the time bank.
+    pub turn: u64,
+    /// What is left of the time bank, if the game has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bank: Option<u64>,
+}
+
+/// What a player can see in a game with fog of war.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Visibility {
+    /// Names of the visible planets
+    pub planets: Vec<String>,
+    /// Ids of the visible expeditions
+    pub expeditions: Vec<u64>,
+}
+
+/// How combat on a planet is resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CombatRule {
+    /// The largest fleet wins, losing as many ships as the other fleets had.
+    #[default]
+    LargestFleet,
+    /// The largest fleet wins, but loses fewer ships the larger it is:
+    /// fighting a fleet of `n` ships with `m` ships costs `n * n / m` ships.
+    ProportionalAttrition,
+    /// The fleet defending the planet is stronger by the given percentage.
+    DefenderBonus(u64),
+}
+
+/// How the winner of a game is decided.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum Victory {
+    /// The last player standing wins, at max turns all living players win.
+    #[default]
+    LastStanding,
+    /// At max turns, the living player with the most ships wins.
+    MostShips,
+    /// At max turns, the living player with the most planets wins, ships break ties.
+    MostPlanets,
+    /// Owning this fraction of all planets wins immediately, at max turns it is most planets.
+    Domination(f64),
+}
+
+/// The first message a player receives, before the game starts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameInfo {
+    pub protocol_version: u64,
+    /// The number of the player in the game.
+    /// States are rotated for every player, so the player is player 1 in the states it receives.
+    pub player: usize,
+    /// Number of players
+    pub players: usize,
+    pub map: String,
+    pub max_turns: u64,
+    /// In milliseconds
+    pub turn_timeout: u64,
+    /// In milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_bank: Option<u64>,
+    pub combat: CombatRule,
+    pub victory: Victory,
+    /// How far players can see, there is no fog of war without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision: Option<f64>,
+    /// What the server can do on request, a player asks for these in its hello.
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// All planets of the map, also the ones hidden by fog of war.
+    /// Compact states and commands refer to planets by their index in this list.
+    pub planets: Vec<MapPlanet>,
+    /// The number of turns an expedition takes between two planets, by their indices.
+    pub distances: Vec<Vec<u64>>,
+}
+
+/// A player's answer to the welcome message, all fields are optional.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Hello {
+    /// The protocol version the player was written for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<u64>,
+    /// The capabilities of the server the player wants to use.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CommandError {
+    NotEnoughShips,
+    OriginNotOwned,
+    ZeroShipMove,
+    OriginDoesNotExist,
+    DestinationDoesNotExist,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerCommand {
+    pub command: Command,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<CommandError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type", content = "value")]
+pub enum PlayerAction {
+    Timeout,
+    ParseError(String),
+    Commands(Vec<PlayerCommand>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type", content = "content")]
+pub enum ServerMessage {
+    /// What the game is about, before the first state.
+    Welcome(GameInfo),
+    /// Game state in current turn
+    GameState(State),
+    /// Game state in current turn, for players that asked for compact states.
+    CompactState(CompactState),
+    /// The action that was performed
+    PlayerAction(PlayerAction),
+    /// The game is over, and this is the concluding state.
+    FinalState(State),
+}
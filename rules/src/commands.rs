@@ -0,0 +1,87 @@
+//! Checking and executing the commands of players, the way the server does.
+
+use std::collections::HashMap;
+
+use crate::protocol::{self as proto, CommandError, PlanetRef};
+use crate::rules::{Dispatch, PlanetWars};
+
+/// Maps planet names to their ids, used to resolve the planets in player commands.
+pub fn planet_map(state: &PlanetWars) -> HashMap<String, usize> {
+    state
+        .planets
+        .iter()
+        .map(|p| (p.name.clone(), p.id))
+        .collect()
+}
+
+/// Dispatches the valid commands of a player, in order.
+/// Every command is returned with its error, if it was invalid.
+pub fn execute_commands(
+    state: &mut PlanetWars,
+    planet_map: &HashMap<String, usize>,
+    player_num: usize,
+    commands: Vec<proto::Command>,
+) -> Vec<proto::PlayerCommand> {
+    commands
+        .into_iter()
+        .map(
+            |command| match check_valid_command(state, planet_map, player_num, &command) {
+                Ok(dispatch) => {
+                    state.dispatch(&dispatch);
+                    proto::PlayerCommand {
+                        command,
+                        error: None,
+                    }
+                }
+                Err(error) => proto::PlayerCommand {
+                    command,
+                    error: Some(error),
+                },
+            },
+        )
+        .collect()
+}
+
+/// Checks a command of a player against the current state,
+/// commands are only valid for the state they are dispatched in.
+pub fn check_valid_command(
+    state: &PlanetWars,
+    planet_map: &HashMap<String, usize>,
+    player_num: usize,
+    mv: &proto::Command,
+) -> Result<Dispatch, CommandError> {
+    let origin_id =
+        planet_id(state, planet_map, &mv.origin).ok_or(CommandError::OriginDoesNotExist)?;
+
+    let target_id = planet_id(state, planet_map, &mv.destination)
+        .ok_or(CommandError::DestinationDoesNotExist)?;
+
+    if state.planets[origin_id].owner() != Some(player_num) {
+        return Err(CommandError::OriginNotOwned);
+    }
+
+    if state.planets[origin_id].ship_count() < mv.ship_count {
+        return Err(CommandError::NotEnoughShips);
+    }
+
+    if mv.ship_count == 0 {
+        return Err(CommandError::ZeroShipMove);
+    }
+
+    Ok(Dispatch {
+        origin: origin_id,
+        target: target_id,
+        ship_count: mv.ship_count,
+    })
+}
+
+fn planet_id(
+    state: &PlanetWars,
+    planet_map: &HashMap<String, usize>,
+    planet: &PlanetRef,
+) -> Option<usize> {
+    match planet {
+        PlanetRef::Index(index) => Some(*index).filter(|&i| i < state.planets.len()),
+        PlanetRef::Name(name) => planet_map.get(name).copied(),
+    }
+}
@@ -0,0 +1,16 @@
+//! The planetwars game rules, without the server.
+//!
+//! The server plays its games with this crate, so bots that depend on it
+//! can search ahead with exactly the same rules, see `Simulation`.
+
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+pub mod commands;
+pub mod rules;
+pub mod serializer;
+pub mod simulation;
+
+pub use planetwars_protocol as protocol;
+pub use simulation::Simulation;
@@ -0,0 +1,575 @@
+pub use planetwars_protocol::{CombatRule, Victory};
+
+/// The planet wars game rules.
+#[derive(Debug, Clone)]
+pub struct PlanetWars {
+    pub players: Vec<Player>,
+    pub planets: Vec<Planet>,
+    pub expeditions: Vec<Expedition>,
+    // How many expeditions were already dispatched.
+    // This is needed for assigning expedition identifiers.
+    pub expedition_num: u64,
+    pub turn_num: u64,
+    pub max_turns: u64,
+    pub combat: CombatRule,
+    pub victory: Victory,
+    // How far players can see, everything is visible when there is no fog of war.
+    pub vision: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Player {
+    pub id: usize,
+    pub alive: bool,
+    /// The turn the player died in.
+    pub eliminated: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    pub owner: Option<usize>,
+    pub ship_count: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Planet {
+    pub id: usize,
+    pub name: String,
+    pub fleets: Vec<Fleet>,
+    pub x: f64,
+    pub y: f64,
+    pub growth: u64,
+    pub radius: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expedition {
+    pub id: u64,
+    pub origin: usize,
+    pub target: usize,
+    pub fleet: Fleet,
+    pub turns_remaining: u64,
+}
+
+/// The place of a player at the end of the game.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Ranked {
+    pub player: usize,
+    /// 1 is best, tied players share a rank.
+    pub rank: usize,
+    /// Ships for last standing and most ships, planets otherwise.
+    pub score: u64,
+}
+
+/// How combat rules resolve combat, the rules themselves are part of the protocol.
+trait Combat {
+    /// How many enemy ships a fleet can destroy.
+    fn strength(&self, fleet: &Fleet, defending: bool) -> u64;
+
+    /// How many ships a fleet loses when fighting a fleet with given strength.
+    fn losses(&self, fleet: &Fleet, defending: bool, strength: u64) -> u64;
+}
+
+// Computed in u128, so no fleet size or bonus can overflow.
+impl Combat for CombatRule {
+    fn strength(&self, fleet: &Fleet, defending: bool) -> u64 {
+        match *self {
+            CombatRule::DefenderBonus(bonus) if defending => {
+                let strength = fleet.ship_count as u128 * (100 + bonus as u128) / 100;
+                strength.min(u64::MAX as u128) as u64
+            }
+            _ => fleet.ship_count,
+        }
+    }
+
+    fn losses(&self, fleet: &Fleet, defending: bool, strength: u64) -> u64 {
+        let strength = strength as u128;
+        let losses = match *self {
+            CombatRule::LargestFleet => strength,
+            CombatRule::ProportionalAttrition if fleet.ship_count > 0 => {
+                // round up, so equal fleets still destroy each other
+                (strength * strength).div_ceil(fleet.ship_count as u128)
+            }
+            CombatRule::ProportionalAttrition => 0,
+            CombatRule::DefenderBonus(bonus) if defending => {
+                let bonus = bonus as u128;
+                (strength * 100 + 99 + bonus) / (100 + bonus)
+            }
+            CombatRule::DefenderBonus(_) => strength,
+        };
+        losses.min(fleet.ship_count as u128) as u64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dispatch {
+    pub origin: usize,
+    pub target: usize,
+    pub ship_count: u64,
+}
+
+impl PlanetWars {
+    pub fn dispatch(&mut self, dispatch: &Dispatch) {
+        let distance = self.planets[dispatch.origin].distance(&self.planets[dispatch.target]);
+
+        let origin = &mut self.planets[dispatch.origin];
+        origin.fleets[0].ship_count -= dispatch.ship_count;
+
+        let expedition = Expedition {
+            id: self.expedition_num,
+            origin: dispatch.origin,
+            target: dispatch.target,
+            turns_remaining: distance,
+            fleet: Fleet {
+                owner: origin.owner(),
+                ship_count: dispatch.ship_count,
+            },
+        };
+
+        // increment counter
+        self.expedition_num += 1;
+        self.expeditions.push(expedition);
+    }
+
+    /// Removes a player from the game: its planets become neutral, its expeditions disappear.
+    pub fn disqualify(&mut self, player_num: usize) {
+        for planet in self.planets.iter_mut() {
+            for fleet in planet.fleets.iter_mut() {
+                if fleet.owner == Some(player_num) {
+                    fleet.owner = None;
+                }
+            }
+        }
+        self.expeditions
+            .retain(|exp| exp.fleet.owner != Some(player_num));
+        self.players[player_num].alive = false;
+        self.players[player_num].eliminated = Some(self.turn_num);
+    }
+
+    // Play one step of the game
+    pub fn step(&mut self) {
+        self.turn_num += 1;
+
+        // Fleets that were orbiting a planet before expeditions arrive are defending it.
+        let defended = self.planets.iter().map(|p| !p.fleets.is_empty()).collect();
+
+        let were_alive = self.living_players();
+
+        // Initially mark all players dead, re-marking them as alive once we
+        // encounter a sign of life.
+        for player in self.players.iter_mut() {
+            player.alive = false;
+        }
+
+        self.step_expeditions();
+        self.resolve_combat(defended);
+
+        for player_num in were_alive {
+            let player = &mut self.players[player_num];
+            if !player.alive {
+                player.eliminated = Some(self.turn_num);
+            }
+        }
+    }
+
+    pub fn repopulate(&mut self) {
+        for planet in self.planets.iter_mut() {
+            if planet.owner().is_some() {
+                planet.fleets[0].ship_count += planet.growth;
+            }
+        }
+    }
+
+    fn step_expeditions(&mut self) {
+        let mut i = 0;
+        let exps = &mut self.expeditions;
+        while i < exps.len() {
+            // compare with 1 to avoid issues with planet distance 0
+            if exps[i].turns_remaining <= 1 {
+                // remove expedition from expeditions, and add to fleet
+                let exp = exps.swap_remove(i);
+                let planet = &mut self.planets[exp.target];
+                planet.orbit(exp.fleet);
+            } else {
+                exps[i].turns_remaining -= 1;
+                if let Some(owner_num) = exps[i].fleet.owner {
+                    // owner has an expedition in progress; this is a sign of life.
+                    self.players[owner_num].alive = true;
+                }
+
+                // proceed to next expedition
+                i += 1;
+            }
+        }
+    }
+
+    fn resolve_combat(&mut self, defended: Vec<bool>) {
+        for (planet, defended) in self.planets.iter_mut().zip(defended) {
+            planet.resolve_combat(self.combat, defended);
+            if let Some(owner_num) = planet.owner() {
+                // owner owns a planet; this is a sign of life.
+                self.players[owner_num].alive = true;
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        let remaining = self.players.iter().filter(|p| p.alive).count();
+        remaining < 2 || self.turn_num >= self.max_turns || self.dominated()
+    }
+
+    /// Whether a player owns enough planets to win by domination.
+    fn dominated(&self) -> bool {
+        match self.victory {
+            Victory::Domination(fraction) => self
+                .players
+                .iter()
+                .any(|p| self.holdings(p.id).0 as f64 >= fraction * self.planets.len() as f64),
+            _ => false,
+        }
+    }
+
+    /// The planets and ships a player owns, ships in expeditions included.
+    pub fn holdings(&self, player_num: usize) -> (u64, u64) {
+        let owned = |fleet: &Fleet| fleet.owner == Some(player_num);
+
+        let planets = self
+            .planets
+            .iter()
+            .filter(|p| p.owner() == Some(player_num))
+            .count() as u64;
+        let ships = self
+            .planets
+            .iter()
+            .flat_map(|p| p.fleets.iter())
+            .chain(self.expeditions.iter().map(|e| &e.fleet))
+            .filter(|f| owned(f))
+            .map(|f| f.ship_count)
+            .sum();
+
+        (planets, ships)
+    }
+
+    /// All players from best to worst, according to the victory condition.
+    /// Living players rank above dead ones, players that died later above earlier ones.
+    pub fn ranking(&self) -> Vec<Ranked> {
+        let key = |player: &Player| {
+            let (planets, ships) = self.holdings(player.id);
+            let eliminated = player.eliminated.unwrap_or(u64::MAX);
+            match self.victory {
+                Victory::LastStanding => (player.alive, 0, 0, eliminated),
+                Victory::MostShips => (player.alive, ships, 0, eliminated),
+                Victory::MostPlanets | Victory::Domination(_) => {
+                    (player.alive, planets, ships, eliminated)
+                }
+            }
+        };
+        let keys: Vec<_> = self.players.iter().map(key).collect();
+
+        let mut ranking: Vec<Ranked> = self
+            .players
+            .iter()
+            .map(|player| {
+                let (planets, ships) = self.holdings(player.id);
+                Ranked {
+                    player: player.id,
+                    rank: 1 + keys.iter().filter(|&k| *k > keys[player.id]).count(),
+                    score: match self.victory {
+                        Victory::LastStanding | Victory::MostShips => ships,
+                        _ => planets,
+                    },
+                }
+            })
+            .collect();
+
+        ranking.sort_by_key(|r| (r.rank, r.player));
+        ranking
+    }
+
+    /// The players that share the first place.
+    pub fn winners(&self) -> Vec<usize> {
+        self.ranking()
+            .into_iter()
+            .filter(|r| r.rank == 1)
+            .map(|r| r.player)
+            .collect()
+    }
+
+    pub fn living_players(&self) -> Vec<usize> {
+        self.players
+            .iter()
+            .filter_map(|p| if p.alive { Some(p.id) } else { None })
+            .collect()
+    }
+}
+
+impl Expedition {
+    /// Where the expedition is now, on the straight line from its origin to its target.
+    pub fn position(&self, planets: &[Planet]) -> (f64, f64) {
+        let origin = &planets[self.origin];
+        let target = &planets[self.target];
+        let distance = origin.distance(target);
+
+        let travelled = if distance > 0 {
+            1.0 - (self.turns_remaining as f64 / distance as f64).min(1.0)
+        } else {
+            1.0
+        };
+
+        (
+            origin.x + (target.x - origin.x) * travelled,
+            origin.y + (target.y - origin.y) * travelled,
+        )
+    }
+}
+
+impl Planet {
+    pub fn owner(&self) -> Option<usize> {
+        self.fleets.first().and_then(|f| f.owner)
+    }
+
+    pub fn ship_count(&self) -> u64 {
+        self.fleets.first().map_or(0, |f| f.ship_count)
+    }
+
+    /// Make a fleet orbit this planet.
+    fn orbit(&mut self, fleet: Fleet) {
+        // If owner already has a fleet present, merge
+        for other in self.fleets.iter_mut() {
+            if other.owner == fleet.owner {
+                other.ship_count += fleet.ship_count;
+                return;
+            }
+        }
+        // else, add fleet to fleets list
+        self.fleets.push(fleet);
+    }
+
+    fn resolve_combat(&mut self, rule: CombatRule, defended: bool) {
+        // The player owning the strongest fleet present will win the combat.
+        // Here, we resolve how many ships he will have left.
+        // note: in the default rule, we could resolve by doing
+        // winner.ship_count -= second_largest.ship_count, but this does not
+        // allow for simple customizations (such as changing combat balance).
+
+        // Fleets are merged per owner, so the owner identifies the defending fleet.
+        let defender = if defended {
+            self.fleets.first().map(|f| f.owner)
+        } else {
+            None
+        };
+        let is_defending = |fleet: &Fleet| Some(fleet.owner) == defender;
+
+        while self.fleets.len() > 1 {
+            self.fleets.sort_by(|a, b| {
+                let a = rule.strength(a, is_defending(a));
+                let b = rule.strength(b, is_defending(b));
+                a.cmp(&b).reverse()
+            });
+
+            let fleet = self.fleets.pop().unwrap();
+            let strength = rule.strength(&fleet, is_defending(&fleet));
+            // destroy some ships
+            for other in self.fleets.iter_mut() {
+                other.ship_count -= rule.losses(other, is_defending(other), strength);
+            }
+
+            // remove dead fleets
+            self.fleets.retain(|f| f.ship_count > 0);
+        }
+    }
+
+    /// The number of turns an expedition takes from this planet to the other one.
+    pub fn distance(&self, other: &Planet) -> u64 {
+        let dx = self.x - other.x;
+        let dy = self.y - other.y;
+        (dx.powi(2) + dy.powi(2)).sqrt().ceil() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fleet(owner: usize, ship_count: u64) -> Fleet {
+        Fleet {
+            owner: Some(owner),
+            ship_count,
+        }
+    }
+
+    /// Owners and ship counts of the fleets left after combat on a planet,
+    /// the first fleet is the one orbiting it.
+    fn combat(rule: CombatRule, defended: bool, fleets: Vec<Fleet>) -> Vec<(usize, u64)> {
+        let mut planet = Planet {
+            id: 0,
+            name: "p0".to_string(),
+            fleets,
+            x: 0.0,
+            y: 0.0,
+            growth: 1,
+            radius: None,
+        };
+        planet.resolve_combat(rule, defended);
+        planet
+            .fleets
+            .iter()
+            .map(|f| (f.owner.unwrap(), f.ship_count))
+            .collect()
+    }
+
+    #[test]
+    fn the_largest_fleet_loses_what_the_others_had() {
+        let rule = CombatRule::LargestFleet;
+        assert_eq!(
+            combat(rule, true, vec![fleet(0, 10), fleet(1, 6)]),
+            vec![(0, 4)]
+        );
+        assert_eq!(
+            combat(rule, true, vec![fleet(0, 10), fleet(1, 6), fleet(2, 3)]),
+            vec![(0, 4)]
+        );
+        assert_eq!(combat(rule, true, vec![fleet(0, 5), fleet(1, 5)]), vec![]);
+    }
+
+    #[test]
+    fn proportional_attrition_spares_large_fleets() {
+        let rule = CombatRule::ProportionalAttrition;
+        assert_eq!(
+            combat(rule, true, vec![fleet(0, 10), fleet(1, 5)]),
+            vec![(0, 7)]
+        );
+        assert_eq!(combat(rule, true, vec![fleet(0, 5), fleet(1, 5)]), vec![]);
+    }
+
+    #[test]
+    fn the_defender_bonus_only_helps_a_defended_planet() {
+        let rule = CombatRule::DefenderBonus(50);
+        assert_eq!(
+            combat(rule, true, vec![fleet(0, 10), fleet(1, 12)]),
+            vec![(0, 2)]
+        );
+        assert_eq!(
+            combat(rule, false, vec![fleet(0, 10), fleet(1, 12)]),
+            vec![(1, 2)]
+        );
+    }
+
+    #[test]
+    fn huge_fleets_and_bonuses_do_not_overflow() {
+        let huge = u64::MAX / 2;
+        assert_eq!(
+            combat(
+                CombatRule::DefenderBonus(u64::MAX),
+                true,
+                vec![fleet(0, huge), fleet(1, 1000)]
+            ),
+            vec![(0, huge - 1)]
+        );
+        assert_eq!(
+            combat(
+                CombatRule::DefenderBonus(50),
+                true,
+                vec![fleet(0, huge), fleet(1, huge)]
+            ),
+            vec![(0, huge - (huge / 3 * 2 + 1))]
+        );
+        assert_eq!(
+            combat(
+                CombatRule::ProportionalAttrition,
+                true,
+                vec![fleet(0, 1 << 41), fleet(1, 1 << 40)]
+            ),
+            vec![(0, (1 << 41) - (1 << 39))]
+        );
+    }
+
+    fn planet(id: usize, owner: Option<usize>, ship_count: u64) -> Planet {
+        Planet {
+            id,
+            name: format!("p{}", id),
+            fleets: vec![Fleet { owner, ship_count }],
+            x: id as f64,
+            y: 0.0,
+            growth: 1,
+            radius: None,
+        }
+    }
+
+    /// Three living players, player 0 owns two planets.
+    fn game(victory: Victory) -> PlanetWars {
+        PlanetWars {
+            players: (0..3)
+                .map(|id| Player {
+                    id,
+                    alive: true,
+                    eliminated: None,
+                })
+                .collect(),
+            planets: vec![
+                planet(0, Some(0), 10),
+                planet(1, Some(0), 1),
+                planet(2, Some(1), 30),
+                planet(3, Some(2), 11),
+            ],
+            expeditions: Vec::new(),
+            expedition_num: 0,
+            turn_num: 0,
+            max_turns: 100,
+            combat: CombatRule::default(),
+            victory,
+            vision: None,
+        }
+    }
+
+    /// Player, rank and score of every player, from best to worst.
+    fn ranking(state: &PlanetWars) -> Vec<(usize, usize, u64)> {
+        state
+            .ranking()
+            .into_iter()
+            .map(|r| (r.player, r.rank, r.score))
+            .collect()
+    }
+
+    #[test]
+    fn the_ranking_follows_the_victory_condition() {
+        assert_eq!(
+            ranking(&game(Victory::LastStanding)),
+            vec![(0, 1, 11), (1, 1, 30), (2, 1, 11)]
+        );
+        assert_eq!(
+            ranking(&game(Victory::MostShips)),
+            vec![(1, 1, 30), (0, 2, 11), (2, 2, 11)]
+        );
+        // ships break the tie between the players with one planet
+        assert_eq!(
+            ranking(&game(Victory::MostPlanets)),
+            vec![(0, 1, 2), (1, 2, 1), (2, 3, 1)]
+        );
+        assert_eq!(
+            ranking(&game(Victory::Domination(0.75))),
+            ranking(&game(Victory::MostPlanets))
+        );
+    }
+
+    #[test]
+    fn dead_players_rank_below_living_ones() {
+        let mut state = game(Victory::MostShips);
+        state.players[1].alive = false;
+        state.players[1].eliminated = Some(3);
+        state.players[2].alive = false;
+        state.players[2].eliminated = Some(5);
+        state.planets[2].fleets[0].owner = None;
+        state.planets[3].fleets[0].owner = None;
+
+        assert_eq!(ranking(&state), vec![(0, 1, 11), (2, 2, 0), (1, 3, 0)]);
+        assert_eq!(state.winners(), vec![0]);
+    }
+
+    #[test]
+    fn domination_ends_the_game() {
+        assert!(game(Victory::Domination(0.5)).is_finished());
+        assert!(!game(Victory::Domination(0.75)).is_finished());
+        assert!(!game(Victory::MostPlanets).is_finished());
+    }
+}
@@ -0,0 +1,311 @@
+use crate::protocol as proto;
+use crate::rules::{Expedition, Planet, PlanetWars};
+
+/// Serialize given gamestate
+pub fn serialize(state: &PlanetWars) -> proto::State {
+    serialize_rotated(state, 0)
+}
+
+/// Serialize given gamestate with player numbers rotated by given offset.
+pub fn serialize_rotated(state: &PlanetWars, offset: usize) -> proto::State {
+    let serializer = Serializer::new(state, offset);
+    serializer.serialize_state()
+}
+
+/// Serialize the gamestate as the given player sees it, rotated for that player.
+/// With fog of war, only the planets and expeditions within vision are included,
+/// and enemy expeditions leave out the planets the player cannot see.
+pub fn serialize_player_view(state: &PlanetWars, player_id: usize) -> proto::State {
+    let mut serializer = Serializer::new(state, player_id);
+    serializer.visibility = visibility(state, player_id);
+    serializer.serialize_state()
+}
+
+/// The compact state the given player sees, rotated for that player.
+pub fn serialize_compact_view(state: &PlanetWars, player_id: usize) -> proto::CompactState {
+    let mut serializer = Serializer::new(state, player_id);
+    serializer.visibility = visibility(state, player_id);
+    serializer.serialize_compact_state()
+}
+
+/// All planets, without what changes during the game.
+pub fn map_planets(state: &PlanetWars) -> Vec<proto::MapPlanet> {
+    state
+        .planets
+        .iter()
+        .map(|planet| proto::MapPlanet {
+            name: planet.name.clone(),
+            x: planet.x,
+            y: planet.y,
+            growth: planet.growth,
+            radius: planet.radius,
+        })
+        .collect()
+}
+
+/// The turns an expedition takes between every two planets.
+pub fn distances(state: &PlanetWars) -> Vec<Vec<u64>> {
+    state
+        .planets
+        .iter()
+        .map(|from| state.planets.iter().map(|to| from.distance(to)).collect())
+        .collect()
+}
+
+/// What the given player can see, `None` when there is no fog of war.
+/// Players see everything within vision of their planets and expeditions.
+pub fn visibility(state: &PlanetWars, player_id: usize) -> Option<proto::Visibility> {
+    let vision = state.vision?;
+
+    let eyes: Vec<(f64, f64)> = state
+        .planets
+        .iter()
+        .filter(|p| p.owner() == Some(player_id))
+        .map(|p| (p.x, p.y))
+        .chain(
+            state
+                .expeditions
+                .iter()
+                .filter(|e| e.fleet.owner == Some(player_id))
+                .map(|e| e.position(&state.planets)),
+        )
+        .collect();
+
+    let sees = |(x, y): (f64, f64)| {
+        eyes.iter()
+            .any(|(ex, ey)| (ex - x).powi(2) + (ey - y).powi(2) <= vision.powi(2))
+    };
+
+    Some(proto::Visibility {
+        planets: state
+            .planets
+            .iter()
+            .filter(|p| sees((p.x, p.y)))
+            .map(|p| p.name.clone())
+            .collect(),
+        expeditions: state
+            .expeditions
+            .iter()
+            .filter(|e| e.fleet.owner == Some(player_id) || sees(e.position(&state.planets)))
+            .map(|e| e.id)
+            .collect(),
+    })
+}
+
+struct Serializer<'a> {
+    state: &'a PlanetWars,
+    player_num_offset: usize,
+    visibility: Option<proto::Visibility>,
+}
+
+impl<'a> Serializer<'a> {
+    fn new(state: &'a PlanetWars, offset: usize) -> Self {
+        Serializer {
+            state,
+            player_num_offset: offset,
+            visibility: None,
+        }
+    }
+
+    fn serialize_state(&self) -> proto::State {
+        proto::State {
+            planets: self
+                .state
+                .planets
+                .iter()
+                .filter(|planet| self.sees_planet(planet))
+                .map(|planet| self.serialize_planet(planet))
+                .collect(),
+            expeditions: self
+                .state
+                .expeditions
+                .iter()
+                .filter(|exp| self.sees_expedition(exp))
+                .map(|exp| self.serialize_expedition(exp))
+                .collect(),
+            time: None,
+        }
+    }
+
+    fn serialize_compact_state(&self) -> proto::CompactState {
+        proto::CompactState {
+            planets: self
+                .state
+                .planets
+                .iter()
+                .filter(|planet| self.sees_planet(planet))
+                .map(|planet| proto::CompactPlanet {
+                    id: planet.id,
+                    owner: planet.owner().map(|id| self.player_num(id)),
+                    ship_count: planet.ship_count(),
+                })
+                .collect(),
+            expeditions: self
+                .state
+                .expeditions
+                .iter()
+                .filter(|exp| self.sees_expedition(exp))
+                .map(|exp| proto::CompactExpedition {
+                    id: exp.id,
+                    owner: self.player_num(exp.fleet.owner.unwrap()),
+                    ship_count: exp.fleet.ship_count,
+                    origin: self.sees_endpoint(exp, exp.origin).then_some(exp.origin),
+                    destination: self.sees_endpoint(exp, exp.target).then_some(exp.target),
+                    turns_remaining: exp.turns_remaining,
+                })
+                .collect(),
+            time: None,
+        }
+    }
+
+    fn sees_planet(&self, planet: &Planet) -> bool {
+        self.visibility
+            .as_ref()
+            .is_none_or(|v| v.planets.contains(&planet.name))
+    }
+
+    fn sees_expedition(&self, exp: &Expedition) -> bool {
+        self.visibility
+            .as_ref()
+            .is_none_or(|v| v.expeditions.contains(&exp.id))
+    }
+
+    /// Players know where their own expeditions come from and go to,
+    /// of other expeditions only the planets they can see.
+    /// Player views are rotated for the player, so the offset is the id of the player.
+    fn sees_endpoint(&self, exp: &Expedition, planet_id: usize) -> bool {
+        exp.fleet.owner == Some(self.player_num_offset)
+            || self.sees_planet(&self.state.planets[planet_id])
+    }
+
+    fn endpoint_name(&self, exp: &Expedition, planet_id: usize) -> Option<String> {
+        self.sees_endpoint(exp, planet_id)
+            .then(|| self.state.planets[planet_id].name.clone())
+    }
+
+    /// Gets the player number for given player id.
+    /// Player numbers are 1-based (as opposed to player ids), They will also be
+    /// rotated based on the number offset for this serializer.
+    fn player_num(&self, player_id: usize) -> usize {
+        let num_players = self.state.players.len();
+        let rotated_id = (player_id + num_players - self.player_num_offset) % num_players;
+        // protocol player ids start at 1
+        rotated_id + 1
+    }
+
+    fn serialize_planet(&self, planet: &Planet) -> proto::Planet {
+        proto::Planet {
+            name: planet.name.clone(),
+            x: planet.x,
+            y: planet.y,
+            owner: planet.owner().map(|id| self.player_num(id)),
+            ship_count: planet.ship_count(),
+            growth: planet.growth,
+            radius: planet.radius,
+        }
+    }
+
+    fn serialize_expedition(&self, exp: &Expedition) -> proto::Expedition {
+        proto::Expedition {
+            id: exp.id,
+            owner: self.player_num(exp.fleet.owner.unwrap()),
+            ship_count: exp.fleet.ship_count,
+            origin: self.endpoint_name(exp, exp.origin),
+            destination: self.endpoint_name(exp, exp.target),
+            turns_remaining: exp.turns_remaining,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{CombatRule, Victory};
+    use crate::rules::{Fleet, Player};
+
+    fn planet(id: usize, x: f64, owner: Option<usize>) -> Planet {
+        Planet {
+            id,
+            name: format!("p{}", id),
+            fleets: vec![Fleet {
+                owner,
+                ship_count: 10,
+            }],
+            x,
+            y: 0.0,
+            growth: 1,
+            radius: None,
+        }
+    }
+
+    fn expedition(id: u64, origin: usize, target: usize, owner: usize, turns: u64) -> Expedition {
+        Expedition {
+            id,
+            origin,
+            target,
+            fleet: Fleet {
+                owner: Some(owner),
+                ship_count: 5,
+            },
+            turns_remaining: turns,
+        }
+    }
+
+    /// Four planets in a row with fog of war. Player 0 sees its planets p0 and p1,
+    /// the expedition of player 1 from p3 to p1 is close enough to p1 to be seen.
+    fn game() -> PlanetWars {
+        PlanetWars {
+            players: (0..2)
+                .map(|id| Player {
+                    id,
+                    alive: true,
+                    eliminated: None,
+                })
+                .collect(),
+            planets: vec![
+                planet(0, 0.0, Some(0)),
+                planet(1, 10.0, Some(0)),
+                planet(2, 20.0, None),
+                planet(3, 30.0, Some(1)),
+            ],
+            expeditions: vec![expedition(0, 3, 1, 1, 4), expedition(1, 0, 2, 0, 18)],
+            expedition_num: 2,
+            turn_num: 0,
+            max_turns: 100,
+            combat: CombatRule::default(),
+            victory: Victory::default(),
+            vision: Some(6.0),
+        }
+    }
+
+    fn endpoints(exp: &proto::Expedition) -> (Option<&str>, Option<&str>) {
+        (exp.origin.as_deref(), exp.destination.as_deref())
+    }
+
+    #[test]
+    fn enemy_expeditions_hide_invisible_planets() {
+        let view = serialize_player_view(&game(), 0);
+        let names: Vec<&str> = view.planets.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["p0", "p1"]);
+
+        assert_eq!(view.expeditions.len(), 2);
+        assert_eq!(endpoints(&view.expeditions[0]), (None, Some("p1")));
+        // Players know where their own expeditions go, also when they cannot see it
+        assert_eq!(endpoints(&view.expeditions[1]), (Some("p0"), Some("p2")));
+
+        let compact = serialize_compact_view(&game(), 0);
+        let compact: Vec<_> = compact
+            .expeditions
+            .iter()
+            .map(|e| (e.origin, e.destination))
+            .collect();
+        assert_eq!(compact, vec![(None, Some(1)), (Some(0), Some(2))]);
+    }
+
+    #[test]
+    fn full_states_keep_every_planet() {
+        let state = serialize(&game());
+        assert_eq!(endpoints(&state.expeditions[0]), (Some("p3"), Some("p1")));
+        assert_eq!(endpoints(&state.expeditions[1]), (Some("p0"), Some("p2")));
+    }
+}
@@ -0,0 +1,438 @@
+//! Playing a game forward, for bots that search ahead.
+//!
+//! A turn is played exactly like the server plays it: the planets grow,
+//! the commands of every player are checked and dispatched in order,
+//! and then the expeditions move and fight.
+
+use std::collections::HashMap;
+
+use crate::commands;
+use crate::protocol::{self as proto, CommandError};
+use crate::rules::{
+    CombatRule, Dispatch, Expedition, Fleet, Planet, PlanetWars, Player, Ranked, Victory,
+};
+
+/// A game that can be played forward, turn by turn.
+/// Clones are independent, so moves can be tried out on a snapshot.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    state: PlanetWars,
+    planet_map: HashMap<String, usize>,
+}
+
+impl Simulation {
+    pub fn new(state: PlanetWars) -> Self {
+        Simulation {
+            planet_map: commands::planet_map(&state),
+            state,
+        }
+    }
+
+    /// The game in a state as a bot receives it, where the bot is player 1.
+    /// Player ids are 0-based, so the bot is player 0 in the simulation.
+    ///
+    /// The state does not tell how many players there are, nor the rules or the turn.
+    /// The players are those up to the highest owner in the state, the simulation uses
+    /// the default rules and never ends on max turns,
+    /// use `with_combat`, `with_victory` and `with_turns_left` to change that.
+    ///
+    /// Planets are numbered in the order of the state. With fog of war, that is not the order
+    /// of the welcome message, and only what the bot can see is simulated: use `from_welcome`
+    /// to refer to planets by their index in the welcome message.
+    pub fn from_state(state: &proto::State) -> Self {
+        let planets = state
+            .planets
+            .iter()
+            .enumerate()
+            .map(|(id, planet)| Planet {
+                id,
+                name: planet.name.clone(),
+                fleets: fleets(planet),
+                x: planet.x,
+                y: planet.y,
+                growth: planet.growth,
+                radius: planet.radius,
+            })
+            .collect();
+
+        Simulation::build(planets, state, 1)
+    }
+
+    /// The game in a state as a bot receives it, with the players, planets and rules
+    /// of the welcome message the bot received, so planets have the indices of the welcome message.
+    /// Planets the bot cannot see are simulated as neutral planets without ships.
+    ///
+    /// The state does not tell the turn, the simulation never ends on max turns,
+    /// use `with_turns_left` to change that.
+    pub fn from_welcome(welcome: &proto::GameInfo, state: &proto::State) -> Self {
+        let mut planets: Vec<Planet> = welcome
+            .planets
+            .iter()
+            .enumerate()
+            .map(|(id, planet)| Planet {
+                id,
+                name: planet.name.clone(),
+                fleets: Vec::new(),
+                x: planet.x,
+                y: planet.y,
+                growth: planet.growth,
+                radius: planet.radius,
+            })
+            .collect();
+        for seen in state.planets.iter() {
+            if let Some(planet) = planets.iter_mut().find(|p| p.name == seen.name) {
+                planet.fleets = fleets(seen);
+            }
+        }
+
+        Simulation::build(planets, state, welcome.players)
+            .with_combat(welcome.combat)
+            .with_victory(welcome.victory)
+    }
+
+    /// The game with the given planets and the expeditions of the state between them.
+    /// There are at least the given number of players, more when the state has higher owners.
+    fn build(planets: Vec<Planet>, state: &proto::State, players: usize) -> Self {
+        let mut game = PlanetWars {
+            players: Vec::new(),
+            planets,
+            expeditions: Vec::new(),
+            expedition_num: 0,
+            turn_num: 0,
+            max_turns: u64::MAX,
+            combat: CombatRule::default(),
+            victory: Victory::default(),
+            vision: None,
+        };
+        let planet_map = commands::planet_map(&game);
+
+        // Expeditions between planets the bot cannot see are left out, as are invalid owners
+        // and expeditions of which fog of war hides where they come from or go to
+        game.expeditions = state
+            .expeditions
+            .iter()
+            .filter_map(|exp| {
+                Some(Expedition {
+                    id: exp.id,
+                    origin: *planet_map.get(exp.origin.as_ref()?)?,
+                    target: *planet_map.get(exp.destination.as_ref()?)?,
+                    fleet: Fleet {
+                        owner: Some(exp.owner.checked_sub(1)?),
+                        ship_count: exp.ship_count,
+                    },
+                    turns_remaining: exp.turns_remaining,
+                })
+            })
+            .collect();
+        game.expedition_num = game.expeditions.iter().map(|e| e.id + 1).max().unwrap_or(0);
+
+        // Players that own something are alive
+        let owners: Vec<usize> = game
+            .planets
+            .iter()
+            .filter_map(|p| p.owner())
+            .chain(game.expeditions.iter().filter_map(|e| e.fleet.owner))
+            .collect();
+        let players = owners
+            .iter()
+            .map(|&owner| owner + 1)
+            .fold(players, usize::max);
+        game.players = (0..players)
+            .map(|id| Player {
+                id,
+                alive: owners.contains(&id),
+                eliminated: None,
+            })
+            .collect();
+
+        Simulation {
+            state: game,
+            planet_map,
+        }
+    }
+
+    pub fn with_combat(mut self, combat: CombatRule) -> Self {
+        self.state.combat = combat;
+        self
+    }
+
+    pub fn with_victory(mut self, victory: Victory) -> Self {
+        self.state.victory = victory;
+        self
+    }
+
+    /// Ends the game after the given number of turns, like max turns does.
+    pub fn with_turns_left(mut self, turns: u64) -> Self {
+        self.state.max_turns = self.state.turn_num + turns;
+        self
+    }
+
+    /// An independent copy to try out moves on.
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    pub fn state(&self) -> &PlanetWars {
+        &self.state
+    }
+
+    /// Checks a command like the server would, if it were dispatched now.
+    pub fn check(
+        &self,
+        player_num: usize,
+        command: &proto::Command,
+    ) -> Result<Dispatch, CommandError> {
+        commands::check_valid_command(&self.state, &self.planet_map, player_num, command)
+    }
+
+    /// Plays one turn with the commands of the given players, in order.
+    /// Returns the commands of every player with their errors, like the server reports them.
+    ///
+    /// Commands are checked after the planets grew, so they may use the ships that grow this turn.
+    pub fn turn(
+        &mut self,
+        turns: Vec<(usize, Vec<proto::Command>)>,
+    ) -> Vec<Vec<proto::PlayerCommand>> {
+        self.state.repopulate();
+        let results = turns
+            .into_iter()
+            .map(|(player_num, commands)| {
+                commands::execute_commands(&mut self.state, &self.planet_map, player_num, commands)
+            })
+            .collect();
+        self.state.step();
+
+        results
+    }
+
+    /// Plays the given number of turns in which nobody sends any ships,
+    /// stopping when the game is finished. Returns the number of turns played.
+    pub fn advance(&mut self, turns: u64) -> u64 {
+        let mut played = 0;
+        while played < turns && !self.state.is_finished() {
+            self.state.repopulate();
+            self.state.step();
+            played += 1;
+        }
+        played
+    }
+
+    /// The ranking of the players once the game is finished.
+    pub fn outcome(&self) -> Option<Vec<Ranked>> {
+        if self.state.is_finished() {
+            Some(self.state.ranking())
+        } else {
+            None
+        }
+    }
+}
+
+/// The ships on a planet of a state, owners in states start at 1.
+/// A planet with an invalid owner is neutral.
+fn fleets(planet: &proto::Planet) -> Vec<Fleet> {
+    let owner = planet.owner.and_then(|owner| owner.checked_sub(1));
+    if planet.ship_count > 0 || owner.is_some() {
+        vec![Fleet {
+            owner,
+            ship_count: planet.ship_count,
+        }]
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::PlanetRef;
+    use crate::serializer;
+
+    fn planet(id: usize, x: f64, owner: Option<usize>, ship_count: u64) -> Planet {
+        Planet {
+            id,
+            name: format!("p{}", id),
+            fleets: vec![Fleet { owner, ship_count }],
+            x,
+            y: 0.0,
+            growth: 2,
+            radius: None,
+        }
+    }
+
+    /// Four planets in a row, the players on both ends.
+    fn game() -> PlanetWars {
+        PlanetWars {
+            players: (0..2)
+                .map(|id| Player {
+                    id,
+                    alive: true,
+                    eliminated: None,
+                })
+                .collect(),
+            planets: vec![
+                planet(0, 0.0, Some(0), 10),
+                planet(1, 3.0, None, 3),
+                planet(2, 6.0, None, 5),
+                planet(3, 9.0, Some(1), 10),
+            ],
+            expeditions: Vec::new(),
+            expedition_num: 0,
+            turn_num: 0,
+            max_turns: 100,
+            combat: CombatRule::default(),
+            victory: Victory::default(),
+            vision: None,
+        }
+    }
+
+    fn command(origin: &str, destination: &str, ship_count: u64) -> proto::Command {
+        proto::Command {
+            origin: origin.into(),
+            destination: destination.into(),
+            ship_count,
+        }
+    }
+
+    /// The state without expedition ids, the simulation numbers expeditions on its own.
+    fn without_ids(state: &PlanetWars) -> proto::State {
+        let mut state = serializer::serialize(state);
+        for expedition in state.expeditions.iter_mut() {
+            expedition.id = 0;
+        }
+        state
+    }
+
+    #[test]
+    fn turns_are_played_like_the_server_plays_them() {
+        let mut server = game();
+        let planet_map = commands::planet_map(&server);
+        let mut sim = Simulation::from_state(&serializer::serialize(&server));
+
+        let turns = vec![
+            vec![
+                (0, vec![command("p0", "p1", 5)]),
+                (1, vec![command("p3", "p2", 8)]),
+            ],
+            vec![
+                (0, vec![command("p0", "p2", 20)]),
+                (1, vec![command("p3", "p0", 4)]),
+            ],
+            vec![
+                (0, vec![command("p1", "p3", 1)]),
+                (1, vec![command("p3", "p3", 0)]),
+            ],
+            vec![(0, Vec::new()), (1, vec![command("p2", "p1", 3)])],
+        ];
+
+        for turn in turns {
+            server.repopulate();
+            let expected: Vec<Vec<proto::PlayerCommand>> = turn
+                .iter()
+                .map(|(player_num, commands)| {
+                    commands::execute_commands(
+                        &mut server,
+                        &planet_map,
+                        *player_num,
+                        commands.clone(),
+                    )
+                })
+                .collect();
+            server.step();
+
+            assert_eq!(sim.turn(turn), expected);
+            assert_eq!(without_ids(sim.state()), without_ids(&server));
+        }
+
+        for _ in 0..20 {
+            server.repopulate();
+            server.step();
+        }
+        assert_eq!(sim.advance(20), 20);
+        assert_eq!(without_ids(sim.state()), without_ids(&server));
+    }
+
+    #[test]
+    fn check_reports_the_errors_of_the_server() {
+        let sim = Simulation::from_state(&serializer::serialize(&game()));
+
+        assert!(sim.check(0, &command("p0", "p1", 10)).is_ok());
+        let errors = [
+            (command("p0", "p1", 11), CommandError::NotEnoughShips),
+            (command("p3", "p1", 1), CommandError::OriginNotOwned),
+            (command("p0", "p1", 0), CommandError::ZeroShipMove),
+            (command("p9", "p1", 1), CommandError::OriginDoesNotExist),
+            (
+                command("p0", "p9", 1),
+                CommandError::DestinationDoesNotExist,
+            ),
+        ];
+        for (command, error) in errors.iter() {
+            assert_eq!(sim.check(0, command).unwrap_err(), *error);
+        }
+    }
+
+    #[test]
+    fn from_welcome_keeps_the_planet_indices_under_fog() {
+        let mut server = game();
+        server.vision = Some(4.0);
+        let view = serializer::serialize_player_view(&server, 0);
+        assert_eq!(view.planets.len(), 2);
+
+        let welcome = proto::GameInfo {
+            protocol_version: proto::PROTOCOL_VERSION,
+            player: 1,
+            players: 2,
+            map: "row".to_string(),
+            max_turns: 100,
+            turn_timeout: 1000,
+            time_bank: None,
+            combat: CombatRule::default(),
+            victory: Victory::default(),
+            vision: server.vision,
+            capabilities: Vec::new(),
+            planets: serializer::map_planets(&server),
+            distances: serializer::distances(&server),
+        };
+        let sim = Simulation::from_welcome(&welcome, &view);
+        let to_enemy = proto::Command {
+            origin: PlanetRef::Index(0),
+            destination: PlanetRef::Index(3),
+            ship_count: 1,
+        };
+
+        assert_eq!(sim.check(0, &to_enemy).unwrap().target, 3);
+        assert_eq!(sim.state().players.len(), 2);
+        assert_eq!(sim.state().planets[3].owner(), None);
+        assert_eq!(
+            Simulation::from_state(&view)
+                .check(0, &to_enemy)
+                .unwrap_err(),
+            CommandError::DestinationDoesNotExist
+        );
+    }
+
+    #[test]
+    fn players_are_derived_from_the_owners() {
+        let mut state = serializer::serialize(&game());
+        // Owners start at 1, an owner 0 is invalid
+        state.planets[1].owner = Some(0);
+        state.planets[2].owner = Some(4);
+        state.expeditions.push(proto::Expedition {
+            id: 0,
+            ship_count: 3,
+            origin: Some("p0".to_string()),
+            destination: Some("p1".to_string()),
+            owner: 0,
+            turns_remaining: 2,
+        });
+
+        let mut sim = Simulation::from_state(&state);
+        assert_eq!(sim.state().players.len(), 4);
+        assert_eq!(sim.state().planets[1].owner(), None);
+        assert!(sim.state().expeditions.is_empty());
+        assert!(sim.state().players[3].alive);
+        assert!(!sim.state().players[2].alive);
+
+        sim.advance(30);
+    }
+}